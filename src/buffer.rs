@@ -1,30 +1,100 @@
-use std::{fs, path::Path};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt::Display,
+    fs,
+    io::{BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::{Context, Result};
-use itertools::Itertools;
+use anyhow::{anyhow, bail, Result};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::pos::Pos;
+use crate::{
+    history::{Edit, History},
+    pos::Pos,
+};
 
 #[derive(Debug, Clone)]
 pub struct Buffer {
-    data: Vec<String>,
+    data: Rope,
     cursor: Pos,
     //pub is debug
     scroll: Pos,
     viewport_size: Pos,
-    endl: String,
+    line_ending: LineEnding,
+    trailing_newline: bool,
+    /// Whether the text was loaded with both LF and CRLF line breaks, which
+    /// saving turns all into `line_ending`
+    mixed_line_endings: bool,
     top_left_corner: Pos,
+    history: History,
+    anchor: Option<Pos>,
+    show_overflow_indicators: bool,
+    wrap: bool,
+    /// Screen row of line `scroll.y` shown at the top of the viewport when
+    /// wrapping, `scroll.x` stays at 0 then
+    scroll_row: usize,
+    /// First line changed since the last call to `take_changed_line`
+    changed_line: Option<usize>,
+    /// Number of edits applied to the text, undos and redos included
+    edit_count: u64,
+    /// Revision and line ending of the text when it was loaded or last saved
+    saved_revision: u64,
+    saved_line_ending: LineEnding,
+}
+
+/// Cursor, selection and scroll of a buffer, so that several windows can show
+/// the same buffer each from their own place
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct View {
+    cursor: Pos,
+    scroll: Pos,
+    scroll_row: usize,
+    anchor: Option<Pos>,
+}
+
+/// Part of a line laid out on a single screen row, which is the whole line
+/// unless wrapping is enabled
+#[derive(Debug, Clone)]
+struct ScreenRow {
+    y: usize,
+    x_range: Range<usize>,
+    /// Whether this is the last row of line `y`
+    last: bool,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Buffer {
     pub fn new() -> Buffer {
         Buffer {
-            data: vec![String::new()],
+            data: Rope::new(),
             cursor: Pos::default(),
-            endl: String::from("\n"),
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+            mixed_line_endings: false,
             scroll: Pos::new(0, 0),
             viewport_size: Pos::new(0, 0),
             top_left_corner: Pos::new(0, 0),
+            history: History::new(),
+            anchor: None,
+            show_overflow_indicators: false,
+            wrap: false,
+            scroll_row: 0,
+            changed_line: None,
+            edit_count: 0,
+            saved_revision: 0,
+            saved_line_ending: LineEnding::Lf,
         }
     }
 
@@ -32,8 +102,46 @@ impl Buffer {
         self.top_left_corner = pos
     }
 
+    pub fn get_top_left_corner(&self) -> Pos {
+        self.top_left_corner
+    }
+
     pub fn content_lines_len(&self) -> usize {
-        self.data.len()
+        self.data.len_lines()
+    }
+
+    /// Text of line `y` without its line break, borrowed from the rope when
+    /// the line is stored in a single chunk
+    pub fn line(&self, y: usize) -> Option<Cow<'_, str>> {
+        let line = self.data.get_line(y)?;
+        let len = line.len_chars();
+        let line = if len > 0 && line.char(len - 1) == '\n' {
+            line.slice(..len - 1)
+        } else {
+            line
+        };
+        Some(line.into())
+    }
+
+    /// Char index in the rope of the grapheme at `pos`
+    pub(crate) fn pos_to_char(&self, pos: Pos) -> usize {
+        let line_start = self.data.line_to_char(pos.y);
+        let line = self.line(pos.y).unwrap_or_default();
+        let byte_index = grapheme_to_byte(&line, pos.x);
+        line_start + line[..byte_index].chars().count()
+    }
+
+    /// Position of the grapheme containing the char at `char_index` in the rope
+    pub(crate) fn char_to_pos(&self, char_index: usize) -> Pos {
+        let y = self.data.char_to_line(char_index);
+        let line = self.line(y).unwrap_or_default();
+        let char_offset = char_index - self.data.line_to_char(y);
+        let byte_index = line
+            .char_indices()
+            .nth(char_offset)
+            .map(|(i, _)| i)
+            .unwrap_or(line.len());
+        Pos::new(byte_to_grapheme(&line, byte_index), y)
     }
 
     pub fn get_viewport_size(&self) -> Pos {
@@ -44,38 +152,306 @@ impl Buffer {
         self.viewport_size = size
     }
 
+    /// Display column of the cursor, which differs from `cursor.x` as soon as
+    /// the line holds wide, zero-width or tab characters
+    pub fn get_cursor_display_col(&self) -> usize {
+        self.line(self.cursor.y)
+            .map(|l| display_col(&l, self.cursor.x))
+            .unwrap_or_default()
+    }
+
     pub fn get_viewport_pos(&self) -> Pos {
-        self.cursor - self.scroll + self.top_left_corner
+        if !self.wrap {
+            return Pos::new(self.get_cursor_display_col(), self.cursor.y) - self.scroll
+                + self.top_left_corner;
+        }
+        let line = self.line(self.cursor.y).unwrap_or_default();
+        let rows = self.line_rows(self.cursor.y);
+        let row_start = rows[row_index(&rows, self.cursor.x)].start;
+        let screen_y = self
+            .visible_rows()
+            .iter()
+            .position(|row| row.y == self.cursor.y && row.x_range.start == row_start)
+            .unwrap_or_default();
+        let x = display_col(&line, self.cursor.x) - display_col(&line, row_start);
+        self.top_left_corner + (x, screen_y)
+    }
+
+    /// Lines currently shown in the viewport, even partially
+    pub fn get_visible_lines(&self) -> Range<usize> {
+        let rows = self.visible_rows();
+        match (rows.first(), rows.last()) {
+            (Some(first), Some(last)) => first.y..last.y + 1,
+            _ => self.scroll.y..self.scroll.y,
+        }
+    }
+
+    /// Line shown on each row of the viewport, from top to bottom, `None` on
+    /// the rows continuing a wrapped line
+    pub fn get_visible_row_lines(&self) -> Vec<Option<usize>> {
+        self.visible_rows()
+            .iter()
+            .map(|row| (row.x_range.start == 0).then_some(row.y))
+            .collect()
+    }
+
+    /// Grapheme ranges of line `y` laid out on successive screen rows
+    fn line_rows(&self, y: usize) -> Vec<Range<usize>> {
+        let line = self.line(y).unwrap_or_default();
+        if self.wrap {
+            wrap_line(&line, self.viewport_size.x)
+        } else {
+            std::iter::once(0..grapheme_len(&line)).collect()
+        }
+    }
+
+    /// Rows shown in the viewport, from top to bottom
+    fn visible_rows(&self) -> Vec<ScreenRow> {
+        let mut rows = vec![];
+        let mut skip = if self.wrap { self.scroll_row } else { 0 };
+        let mut y = self.scroll.y;
+        while rows.len() < self.viewport_size.y && y < self.data.len_lines() {
+            let line_rows = self.line_rows(y);
+            let last = line_rows.len() - 1;
+            rows.extend(
+                line_rows
+                    .into_iter()
+                    .enumerate()
+                    .skip(skip)
+                    .map(|(i, x_range)| ScreenRow {
+                        y,
+                        x_range,
+                        last: i == last,
+                    }),
+            );
+            skip = 0;
+            y += 1;
+        }
+        rows.truncate(self.viewport_size.y);
+        rows
     }
 
     pub fn get_viewport(&self) -> Vec<(Pos, String)> {
         let mut viewport = vec![];
-        for y in self.scroll.y..self.scroll.y + self.viewport_size.y {
-            if let Some(line) = self.data.get(y) {
-                viewport.push((
-                    self.top_left_corner + (0, y) - self.scroll,
-                    line.to_string(),
-                ));
-            }
+        for (screen_y, row) in self.visible_rows().iter().enumerate() {
+            let line = self.line(row.y).unwrap_or_default();
+            let (pos, text) =
+                self.viewport_fragment(&line, row, screen_y, row.x_range.clone(), false);
+            let text = if self.show_overflow_indicators && !self.wrap {
+                let line_width = display_col(&line, usize::MAX);
+                mark_overflow(
+                    &text,
+                    self.scroll.x > 0 && line_width > 0,
+                    line_width > self.scroll.x + self.viewport_size.x,
+                )
+            } else {
+                text
+            };
+            viewport.push((pos, text));
         }
         viewport
     }
 
-    fn cap_scroll(&mut self) {
-        let x = if self.get_cursor().x < self.scroll.x {
-            self.get_cursor().x
-        } else if self.get_cursor().x
-            > (self.scroll.x + self.viewport_size.x.checked_sub(1).unwrap_or_default())
+    /// Visible fragments of the text between `start` and `end`, with the
+    /// terminal position at which each of them has to be printed. Line breaks
+    /// inside the range are shown as a trailing space.
+    pub fn get_viewport_ranges(&self, start: Pos, end: Pos) -> Vec<(Pos, String)> {
+        let mut fragments = vec![];
+        self.push_range_fragments(&self.visible_rows(), start, end, &mut fragments);
+        fragments
+    }
+
+    /// Visible fragments of each of the `(start, end, tag)` spans, as with
+    /// `get_viewport_ranges`, tagged with the tag of their span
+    pub fn get_viewport_spans<T: Copy>(&self, spans: &[(Pos, Pos, T)]) -> Vec<(Pos, String, T)> {
+        let rows = self.visible_rows();
+        let mut fragments = vec![];
+        let mut span_fragments = vec![];
+        for (start, end, tag) in spans {
+            self.push_range_fragments(&rows, *start, *end, &mut span_fragments);
+            fragments.extend(
+                span_fragments
+                    .drain(..)
+                    .map(|(pos, text)| (pos, text, *tag)),
+            );
+        }
+        fragments
+    }
+
+    fn push_range_fragments(
+        &self,
+        rows: &[ScreenRow],
+        start: Pos,
+        end: Pos,
+        fragments: &mut Vec<(Pos, String)>,
+    ) {
+        for (screen_y, row) in rows.iter().enumerate() {
+            if row.y < start.y || row.y > end.y {
+                continue;
+            }
+            let Some(line) = self.line(row.y) else {
+                break;
+            };
+            let start_x = if row.y == start.y {
+                start.x.max(row.x_range.start)
+            } else {
+                row.x_range.start
+            };
+            let end_x = if row.y == end.y {
+                end.x.min(row.x_range.end)
+            } else {
+                row.x_range.end
+            };
+            if start_x > end_x {
+                continue;
+            }
+            let line_break = row.y < end.y && row.last;
+            let (pos, text) =
+                self.viewport_fragment(&line, row, screen_y, start_x..end_x, line_break);
+            if !text.is_empty() {
+                fragments.push((pos, text));
+            }
+        }
+    }
+
+    /// Rendered graphemes `x_range` of `line`, which is laid out on `row`
+    /// printed at `screen_y`, clipped to the columns in view, with the terminal
+    /// position of the first one. A trailing space stands for the line break if
+    /// `line_break` is set.
+    fn viewport_fragment(
+        &self,
+        line: &str,
+        row: &ScreenRow,
+        screen_y: usize,
+        x_range: Range<usize>,
+        line_break: bool,
+    ) -> (Pos, String) {
+        let (col, mut text) = render_graphemes(line, x_range);
+        if line_break {
+            text.push(' ');
+        }
+        let row_col = if self.wrap {
+            display_col(line, row.x_range.start)
+        } else {
+            self.scroll.x
+        };
+        let (col, text) = clip_columns(col, &text, row_col..row_col + self.viewport_size.x);
+        (self.top_left_corner + (col - row_col, screen_y), text)
+    }
+
+    /// Lays long lines out across several screen rows instead of scrolling
+    /// horizontally
+    pub fn set_wrap(&mut self, wrap: bool) {
+        if wrap != self.wrap {
+            self.wrap = wrap;
+            self.scroll.x = 0;
+            self.scroll_row = 0;
+        }
+    }
+
+    /// Shows `<` and `>` at the edges of lines with content scrolled out of view
+    pub fn set_show_overflow_indicators(&mut self, show: bool) {
+        self.show_overflow_indicators = show;
+    }
+
+    /// Selected range as ordered `(start, end)` positions, if any
+    pub fn get_selection(&self) -> Option<(Pos, Pos)> {
+        let anchor = self.anchor?;
+        let cursor = self.cursor;
+        match (anchor.y, anchor.x).cmp(&(cursor.y, cursor.x)) {
+            Ordering::Less => Some((anchor, cursor)),
+            Ordering::Greater => Some((cursor, anchor)),
+            Ordering::Equal => None,
+        }
+    }
+
+    /// Anchors a selection at the cursor, unless one is already anchored
+    pub fn start_selection(&mut self) {
+        if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.anchor = None;
+    }
+
+    pub fn get_selected_text(&self) -> Option<String> {
+        let (start, end) = self.get_selection()?;
+        Some(
+            self.data
+                .slice(self.pos_to_char(start)..self.pos_to_char(end))
+                .to_string(),
+        )
+    }
+
+    /// Deletes the selected text, returns false if nothing was selected
+    pub fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.get_selection() else {
+            return false;
+        };
+        let range = self.pos_to_char(start)..self.pos_to_char(end);
+        self.delete_chars(range, self.cursor);
+        true
+    }
+
+    /// Inserts `text` in place of the selection, or at the cursor if nothing is
+    /// selected, as a single undo step
+    pub fn replace_selection(&mut self, text: &str) -> Result<()> {
+        self.begin_transaction();
+        self.delete_selection();
+        let result = self.add_str_at_cursor(text);
+        self.end_transaction();
+        result
+    }
+
+    /// Moves the cursor to the character displayed at the terminal position `pos`,
+    /// does nothing if `pos` is outside of the viewport
+    pub fn move_cursor_to_viewport_pos(&mut self, pos: Pos) {
+        if pos.x < self.top_left_corner.x
+            || pos.y < self.top_left_corner.y
+            || pos.x >= self.top_left_corner.x + self.viewport_size.x
+            || pos.y >= self.top_left_corner.y + self.viewport_size.y
         {
-            self.get_cursor().x - self.viewport_size.x + 1
+            return;
+        }
+        let target = pos - self.top_left_corner;
+        let rows = self.visible_rows();
+        let Some(row) = rows.get(target.y).or(rows.last()) else {
+            return;
+        };
+        let line = self.line(row.y).unwrap_or_default();
+        let row_col = if self.wrap {
+            display_col(&line, row.x_range.start)
+        } else {
+            self.scroll.x
+        };
+        let x = display_col_to_grapheme(&line, row_col + target.x);
+        // The end of a row that isn't the last one is the start of the next
+        let x = if row.last {
+            x
+        } else {
+            x.min(row.x_range.end - 1)
+        };
+        self.move_cursor(Pos::new(x, row.y));
+    }
+
+    fn cap_scroll(&mut self) {
+        if self.wrap {
+            self.cap_scroll_rows();
+            return;
+        }
+        let cursor_col = self.get_cursor_display_col();
+        let x = if cursor_col < self.scroll.x {
+            cursor_col
+        } else if cursor_col > (self.scroll.x + self.viewport_size.x.saturating_sub(1)) {
+            cursor_col - self.viewport_size.x + 1
         } else {
             self.scroll.x
         };
         let y = if self.get_cursor().y < self.scroll.y {
             self.get_cursor().y
-        } else if self.get_cursor().y
-            > (self.scroll.y + self.viewport_size.y.checked_sub(1).unwrap_or_default())
-        {
+        } else if self.get_cursor().y > (self.scroll.y + self.viewport_size.y.saturating_sub(1)) {
             self.get_cursor().y - self.viewport_size.y + 1
         } else {
             self.scroll.y
@@ -83,6 +459,34 @@ impl Buffer {
         self.scroll = Pos::new(x, y);
     }
 
+    /// Scrolls by screen rows until the row holding the cursor is in view
+    fn cap_scroll_rows(&mut self) {
+        let rows = self.line_rows(self.cursor.y);
+        let row = row_index(&rows, self.cursor.x);
+        self.scroll.x = 0;
+        if (self.cursor.y, row) < (self.scroll.y, self.scroll_row) {
+            self.scroll.y = self.cursor.y;
+            self.scroll_row = row;
+            return;
+        }
+        // Topmost row that still shows the cursor row at the bottom
+        let (mut y, mut row) = (self.cursor.y, row);
+        for _ in 1..self.viewport_size.y {
+            if row > 0 {
+                row -= 1;
+            } else if y > 0 {
+                y -= 1;
+                row = self.line_rows(y).len() - 1;
+            } else {
+                break;
+            }
+        }
+        if (y, row) > (self.scroll.y, self.scroll_row) {
+            self.scroll.y = y;
+            self.scroll_row = row;
+        }
+    }
+
     //Todo: change to be base fn
     pub fn move_up_n(&mut self, n: usize) {
         for _ in 0..n {
@@ -98,18 +502,71 @@ impl Buffer {
     }
 
     pub fn move_up(&mut self) {
-        let pos = Pos::new(
-            self.cursor.x,
-            self.cursor.y.checked_sub(1).unwrap_or(self.cursor.y),
-        );
-        self.move_cursor(pos);
-        self.cap_scroll();
+        if self.wrap {
+            self.move_screen_row(false);
+            return;
+        }
+        let y = self.cursor.y.checked_sub(1).unwrap_or(self.cursor.y);
+        self.move_cursor_keep_display_col(y);
     }
 
     pub fn move_down(&mut self) {
-        let pos = Pos::new(self.cursor.x, self.cursor.y + 1);
-        self.move_cursor(pos);
-        self.cap_scroll();
+        if self.wrap {
+            self.move_screen_row(true);
+            return;
+        }
+        self.move_cursor_keep_display_col(self.cursor.y + 1);
+    }
+
+    /// Moves to the next or previous screen row of a wrapped line, staying at
+    /// the same column relative to the start of the row
+    fn move_screen_row(&mut self, down: bool) {
+        let rows = self.line_rows(self.cursor.y);
+        let row = row_index(&rows, self.cursor.x);
+        let line = self.line(self.cursor.y).unwrap_or_default();
+        let col = display_col(&line, self.cursor.x) - display_col(&line, rows[row].start);
+        let (y, row) = if down {
+            if row + 1 < rows.len() {
+                (self.cursor.y, row + 1)
+            } else if self.cursor.y + 1 < self.data.len_lines() {
+                (self.cursor.y + 1, 0)
+            } else {
+                return;
+            }
+        } else if row > 0 {
+            (self.cursor.y, row - 1)
+        } else if self.cursor.y > 0 {
+            (self.cursor.y - 1, usize::MAX)
+        } else {
+            return;
+        };
+        let line = self.line(y).unwrap_or_default();
+        let rows = self.line_rows(y);
+        let row = row.min(rows.len() - 1);
+        let x = display_col_to_grapheme(&line, display_col(&line, rows[row].start) + col);
+        let x = if row + 1 < rows.len() {
+            x.min(rows[row].end - 1)
+        } else {
+            x
+        };
+        self.move_cursor(Pos::new(x, y));
+    }
+
+    /// Moves to line `y`, staying as close as possible to the current display
+    /// column so vertical moves don't drift across lines of wide characters
+    fn move_cursor_keep_display_col(&mut self, y: usize) {
+        let col = self.get_cursor_display_col();
+        let y = y.min(self.data.len_lines().saturating_sub(1));
+        let x = self
+            .line(y)
+            .map(|l| display_col_to_grapheme(&l, col))
+            .unwrap_or_default();
+        self.move_cursor(Pos::new(x, y));
+    }
+
+    /// Length of line `y` in grapheme clusters, 0 if there is no such line
+    pub fn line_len(&self, y: usize) -> usize {
+        self.line(y).map(|l| grapheme_len(&l)).unwrap_or_default()
     }
 
     pub fn move_left_n(&mut self, n: usize) -> usize {
@@ -120,18 +577,13 @@ impl Buffer {
                 break;
             }
             if cursor.x == 0 {
-                let prev_line_len = self
-                    .data
-                    .get(cursor.y - 1)
-                    .map(|l| l.len())
-                    .unwrap_or_default();
-                cursor = Pos::new(prev_line_len, cursor.y - 1);
+                cursor = Pos::new(self.line_len(cursor.y - 1), cursor.y - 1);
                 moved += 1;
                 continue;
             }
             if cursor.x >= (n - moved) {
                 cursor.x -= n - moved;
-                moved += n - moved;
+                moved = n;
             } else {
                 moved += cursor.x;
                 cursor = Pos::new(0, cursor.y);
@@ -146,19 +598,13 @@ impl Buffer {
     pub fn move_right_n(&mut self, n: usize) -> usize {
         let mut moved = 0;
         let mut cursor = self.cursor;
-        let last_cursor_y_pos = self.data.len().checked_sub(1).unwrap_or_default();
-        let file_end_pos = Pos::new(
-            self.data
-                .get(last_cursor_y_pos)
-                .map(|l| l.len())
-                .unwrap_or_default(),
-            last_cursor_y_pos,
-        );
+        let last_cursor_y_pos = self.data.len_lines().saturating_sub(1);
+        let file_end_pos = Pos::new(self.line_len(last_cursor_y_pos), last_cursor_y_pos);
         while moved < n {
             if cursor == file_end_pos {
                 break;
             }
-            let current_line_len = self.data.get(cursor.y).map(|l| l.len()).unwrap();
+            let current_line_len = self.line_len(cursor.y);
             if cursor.x == current_line_len {
                 cursor = Pos::new(0, cursor.y + 1);
                 moved += 1;
@@ -166,9 +612,9 @@ impl Buffer {
             }
             if (current_line_len - cursor.x) >= (n - moved) {
                 cursor.x += n - moved;
-                moved += n - moved;
+                moved = n;
             } else {
-                moved += cursor.x;
+                moved += current_line_len - cursor.x;
                 cursor = Pos::new(current_line_len, cursor.y);
             }
         }
@@ -187,11 +633,9 @@ impl Buffer {
     }
 
     pub fn move_line_end(&mut self) -> usize {
-        let mut cursor = self.get_cursor();
-        let current_line_len = self.data.get(cursor.y).map(|l| l.len()).unwrap_or_default();
-        let moved = current_line_len - cursor.x;
-        cursor.x = current_line_len;
-        self.cursor = cursor;
+        let current_line_len = self.line_len(self.cursor.y);
+        let moved = current_line_len - self.cursor.x;
+        self.cursor.x = current_line_len;
         self.cap_scroll();
         moved
     }
@@ -204,21 +648,39 @@ impl Buffer {
     }
 
     pub fn move_cursor(&mut self, pos: Pos) {
-        let y = pos
-            .y
-            .min(self.data.len().checked_sub(1).unwrap_or_default());
-        let x = self
-            .data
-            .get(y)
-            .map(|l| l.len())
-            .unwrap_or_default()
-            .min(pos.x);
-        self.cursor = Pos::new(x, y);
+        self.cursor = self.clamp_pos(pos);
         self.cap_scroll();
     }
 
+    /// Closest position to `pos` within the text
+    fn clamp_pos(&self, pos: Pos) -> Pos {
+        let y = pos.y.min(self.data.len_lines().saturating_sub(1));
+        Pos::new(self.line_len(y).min(pos.x), y)
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            cursor: self.cursor,
+            scroll: self.scroll,
+            scroll_row: self.scroll_row,
+            anchor: self.anchor,
+        }
+    }
+
+    /// Moves to `view`, brought back within the text as it may have been
+    /// edited from another view since
+    pub fn set_view(&mut self, view: View) {
+        self.scroll = Pos::new(
+            view.scroll.x,
+            view.scroll.y.min(self.data.len_lines().saturating_sub(1)),
+        );
+        self.scroll_row = view.scroll_row.min(self.line_rows(self.scroll.y).len() - 1);
+        self.anchor = view.anchor.map(|anchor| self.clamp_pos(anchor));
+        self.move_cursor(view.cursor);
+    }
+
     pub fn move_cursor_relative(&mut self, pos: Pos) {
-        self.cursor = self.cursor + pos
+        self.cursor += pos
     }
 
     pub fn get_cursor(&self) -> Pos {
@@ -226,130 +688,680 @@ impl Buffer {
     }
 
     pub fn add_str_at_cursor(&mut self, text: &str) -> Result<()> {
-        let cursor = self.get_cursor();
-        if let Some(line) = self.data.get_mut(cursor.y) {
-            line.insert_str(cursor.x, text);
-        } else {
-            self.data.push(text.to_string());
-        }
-        self.move_cursor(cursor + (text.len(), 0));
+        let cursor_before = self.get_cursor();
+        let char_index = self.pos_to_char(cursor_before);
+        self.apply_edit(&Edit::Insert {
+            at: char_index,
+            text: text.to_string(),
+        });
+        // Inserted text may merge with its neighbours (e.g. combining marks),
+        // so the new cursor is recomputed from the char position.
+        self.cursor = self.char_to_pos(char_index + text.chars().count());
+        self.history.record(
+            Edit::Insert {
+                at: char_index,
+                text: text.to_string(),
+            },
+            cursor_before,
+            self.cursor,
+        );
         self.cap_scroll();
         Ok(())
     }
 
     pub fn delete_n_chars_front_from_cursor(&mut self, n: usize) -> Result<()> {
-        let moved = self.move_right_n(n);
-        self.delete_n_chars_back_from_cursor(moved)
+        let cursor_before = self.get_cursor();
+        let start = self.pos_to_char(cursor_before);
+        self.move_right_n(n);
+        let end = self.pos_to_char(self.get_cursor());
+        self.delete_chars(start..end, cursor_before);
+        Ok(())
     }
 
     pub fn delete_n_chars_back_from_cursor(&mut self, n: usize) -> Result<()> {
-        let mut deleted = 0;
-        let mut cursor = self.get_cursor();
-        while deleted < n {
-            if cursor == Pos::new(0, 0) {
-                break;
-            }
-
-            if cursor.x == 0 {
-                let current_line = self
-                    .data
-                    .get(cursor.y)
-                    .context("No line at cursor")?
-                    .clone();
-                let prev_line = self
-                    .data
-                    .get(cursor.y - 1)
-                    .context("No line before cursor")?
-                    .clone();
-                self.data.remove(cursor.y);
-                *(self
-                    .data
-                    .get_mut(cursor.y - 1)
-                    .context("No line at cursor")?) = prev_line.clone() + &current_line;
-                cursor = Pos::new(prev_line.len(), cursor.y - 1);
-                deleted += 1;
-                continue;
-            }
+        let cursor_before = self.get_cursor();
+        let end = self.pos_to_char(cursor_before);
+        self.move_left_n(n);
+        let start = self.pos_to_char(self.get_cursor());
+        self.delete_chars(start..end, cursor_before);
+        Ok(())
+    }
 
-            if cursor.x >= (n - deleted) {
-                let current_line = self.data.get(cursor.y).context("No line at cursor")?;
-                *(self.data.get_mut(cursor.y).context("No line at cursor")?) = current_line
-                    .get(0..cursor.x + deleted - n)
-                    .context("Couldn't extract start slice")?
-                    .to_string()
-                    + current_line
-                        .get(cursor.x..current_line.len())
-                        .context("Couldn't extract end slice")?;
-                cursor.x -= n - deleted;
-                deleted += n - deleted;
-            } else {
-                *(self.data.get_mut(cursor.y).context("No line at cursor")?) = String::new();
-                deleted += cursor.x;
-                cursor = Pos::new(0, cursor.y);
-            }
+    /// Removes the chars in `range`, leaving the cursor at its start
+    fn delete_chars(&mut self, range: Range<usize>, cursor_before: Pos) {
+        if range.is_empty() {
+            return;
         }
-
-        self.cursor = cursor;
+        let edit = Edit::Delete {
+            at: range.start,
+            text: self.data.slice(range.clone()).to_string(),
+        };
+        self.apply_edit(&edit);
+        self.cursor = self.char_to_pos(range.start);
+        self.history.record(edit, cursor_before, self.cursor);
         self.cap_scroll();
-        Ok(())
     }
 
-    pub fn add_line_at_cursor(&mut self) -> Result<()> {
-        let cursor = self.get_cursor();
-        if let Some(current_line) = self.data.get(cursor.y).map(|x| x.clone()) {
-            *(self.data.get_mut(cursor.y).context("No line at cursor")?) = current_line
-                .get(cursor.x..current_line.len())
-                .context("Couldn't extract end slice")?
-                .to_string();
-            self.data.insert(
-                cursor.y,
-                current_line
-                    .get(0..cursor.x)
-                    .context("Couldn't extract start slice")?
-                    .to_string(),
-            );
+    /// Replaces the text between `start` and `end` with `text` as a single undo
+    /// step, leaving the cursor after the inserted text
+    pub fn replace_range(&mut self, start: Pos, end: Pos, text: &str) -> Result<()> {
+        self.begin_transaction();
+        let range = self.pos_to_char(start)..self.pos_to_char(end);
+        self.delete_chars(range, self.cursor);
+        self.cursor = start;
+        let result = self.add_str_at_cursor(text);
+        self.end_transaction();
+        result
+    }
+
+    /// Inserts pasted text in place of the selection as a single undo step,
+    /// turning CRLF and lone CR line breaks into the buffer's `\n`
+    pub fn paste_str(&mut self, text: &str) -> Result<()> {
+        let text = if text.contains('\r') {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
         } else {
-            self.data.push(String::new());
-        }
-        self.move_cursor((0, cursor.y + 1).into());
-        self.cap_scroll();
-        Ok(())
+            Cow::Borrowed(text)
+        };
+        self.replace_selection(&text)
+    }
+
+    pub fn add_line_at_cursor(&mut self) -> Result<()> {
+        self.add_str_at_cursor("\n")
     }
 
     pub fn empty_content(&mut self) {
-        self.data = vec![String::new()];
+        self.delete_chars(0..self.data.len_chars(), self.get_cursor());
+        self.cursor = Pos::default();
+        self.cap_scroll();
     }
 
+    /// Applies `edit` to the text only, without touching the cursor or history.
+    /// Any selection is dropped as its positions are no longer meaningful.
+    fn apply_edit(&mut self, edit: &Edit) {
+        self.anchor = None;
+        let (Edit::Insert { at, .. } | Edit::Delete { at, .. }) = edit;
+        let y = self.data.char_to_line(*at);
+        self.changed_line = Some(self.changed_line.map_or(y, |line| line.min(y)));
+        self.edit_count += 1;
+        match edit {
+            Edit::Insert { at, text } => self.data.insert(*at, text),
+            Edit::Delete { at, text } => self.data.remove(*at..*at + text.chars().count()),
+        }
+    }
+
+    /// Grows with every change to the text, unlike `revision` which stays the
+    /// same while typing extends the last transaction
+    pub fn edit_count(&self) -> u64 {
+        self.edit_count
+    }
+
+    /// First line whose text changed since the last call, if any
+    pub fn take_changed_line(&mut self) -> Option<usize> {
+        self.changed_line.take()
+    }
+
+    /// Groups every edit until the matching `end_transaction` into one undo step
+    pub fn begin_transaction(&mut self) {
+        self.history.begin_group(self.cursor);
+    }
+
+    pub fn end_transaction(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Reverts the last transaction, returns false if there was nothing to undo
+    pub fn undo(&mut self) -> bool {
+        let Some(transaction) = self.history.undo() else {
+            return false;
+        };
+        for edit in transaction.edits.iter().rev() {
+            self.apply_edit(&edit.inverse());
+        }
+        self.move_cursor(transaction.cursor_before);
+        true
+    }
+
+    /// Reapplies the last undone transaction, returns false if there was none
+    pub fn redo(&mut self) -> bool {
+        let Some(transaction) = self.history.redo() else {
+            return false;
+        };
+        for edit in transaction.edits.iter() {
+            self.apply_edit(edit);
+        }
+        self.move_cursor(transaction.cursor_after);
+        true
+    }
+
+    /// Loads `s`, remembering its line ending and whether it ends with a line
+    /// break so that `get_contents` gives it back unchanged
     pub fn load_from_str(s: &str) -> Buffer {
         let mut loaded_buffer = Buffer::new();
-        loaded_buffer.data = s.lines().map(|l| l.to_string()).collect_vec();
+        loaded_buffer.line_ending = LineEnding::detect(s);
+        loaded_buffer.saved_line_ending = loaded_buffer.line_ending;
+        loaded_buffer.mixed_line_endings = mixes_line_endings(s);
+        let (s, trailing_newline) = match s
+            .strip_suffix(loaded_buffer.line_ending.as_str())
+            .or_else(|| s.strip_suffix('\n'))
+        {
+            Some(s) => (s, true),
+            // An empty file gets a line break once written to, like a new
+            // buffer does
+            None => (s, s.is_empty()),
+        };
+        loaded_buffer.trailing_newline = trailing_newline;
+        loaded_buffer.data = Rope::from_str(&normalize_line_endings(s));
         loaded_buffer
     }
 
+    /// Replaces the whole text with `s` as a single undo step, taking its line
+    /// ending and final line break like `load_from_str` does
+    pub fn replace_contents(&mut self, s: &str) -> Result<()> {
+        let loaded = Buffer::load_from_str(s);
+        self.line_ending = loaded.line_ending;
+        self.trailing_newline = loaded.trailing_newline;
+        self.mixed_line_endings = loaded.mixed_line_endings;
+        let end = self.char_to_pos(self.data.len_chars());
+        self.replace_range(Pos::default(), end, &loaded.data.to_string())?;
+        self.move_cursor(Pos::default());
+        Ok(())
+    }
+
+    /// Copy of the text alone, without cursor nor history, which is cheap as
+    /// the rope is shared until either copy changes
+    pub fn text_snapshot(&self) -> Buffer {
+        Buffer {
+            data: self.data.clone(),
+            line_ending: self.line_ending,
+            trailing_newline: self.trailing_newline,
+            ..Buffer::new()
+        }
+    }
+
+    pub fn get_line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Whether the text still has the mixed LF and CRLF line breaks it was
+    /// loaded with, until saving makes them all the same
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    /// Line ending used for every line break when writing the buffer out
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Identifies the current state of the text, changes with every edit and
+    /// comes back to a previous value when undoing or redoing to that state
+    pub fn revision(&self) -> u64 {
+        self.history.revision()
+    }
+
+    /// Whether saving would write something different from what was loaded
+    /// or last saved
+    pub fn is_modified(&self) -> bool {
+        self.history.revision() != self.saved_revision || self.line_ending != self.saved_line_ending
+    }
+
+    pub fn has_trailing_newline(&self) -> bool {
+        self.trailing_newline
+    }
+
+    /// Writes the lines separated by the buffer's line ending, chunk by chunk
+    /// so that large buffers are never copied into a single string
+    pub fn write_contents<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let endl = self.line_ending.as_str().as_bytes();
+        for chunk in self.data.chunks() {
+            let mut lines = chunk.split('\n');
+            if let Some(first) = lines.next() {
+                writer.write_all(first.as_bytes())?;
+            }
+            for line in lines {
+                writer.write_all(endl)?;
+                writer.write_all(line.as_bytes())?;
+            }
+        }
+        if self.trailing_newline {
+            writer.write_all(endl)?;
+        }
+        Ok(())
+    }
+
     pub fn get_contents(&self) -> String {
-        self.data
-            .iter()
-            .map(|line| (line.to_string() + &self.endl))
-            .join("")
+        let mut contents = Vec::with_capacity(self.data.len_bytes() + self.data.len_lines());
+        self.write_contents(&mut contents)
+            .expect("Writing to a Vec can't fail");
+        String::from_utf8(contents).expect("Buffer contents are valid UTF-8")
     }
 
     pub fn load_from_file<P>(path: P) -> Result<Buffer>
     where
         P: AsRef<Path>,
     {
-        let mut loaded_buffer = Buffer::new();
-        loaded_buffer.data = fs::read_to_string(path)?
-            .lines()
-            .map(|l| l.to_string())
-            .collect_vec();
-        Ok(loaded_buffer)
+        Ok(Buffer::load_from_str(&fs::read_to_string(path)?))
     }
 
-    pub fn save_to_file<P>(&self, path: P) -> Result<()>
+    /// Writes the buffer to a temporary file next to `path` then renames it
+    /// over `path`, so that the file is never left half written. The file a
+    /// symlink points to is the one replaced, keeping its permissions and,
+    /// where allowed, its owner.
+    pub fn save_to_file<P>(&mut self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        fs::write(path, self.get_contents().as_bytes())?;
+        let path = resolve_symlinks(path.as_ref());
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("`{}` isn't a file name", path.display()))?;
+        let existing = fs::metadata(&path).ok();
+        // Renaming over it would succeed, as only the directory has to be
+        // writable, and silently bypass the protection
+        if existing
+            .as_ref()
+            .is_some_and(|metadata| metadata.permissions().readonly())
+        {
+            bail!("`{}` is read-only", path.display());
+        }
+
+        let permissions = existing.as_ref().map(|metadata| metadata.permissions());
+        let (temp_path, file) =
+            create_temp_file(dir, &file_name.to_string_lossy(), permissions.as_ref())?;
+        let written = (|| -> Result<()> {
+            if let Some(metadata) = &existing {
+                // Before any byte is written, so that the contents are never
+                // readable by more users than the original's
+                file.set_permissions(metadata.permissions())?;
+                preserve_owner(&file, metadata);
+            }
+            let mut writer = BufWriter::new(&file);
+            self.write_contents(&mut writer)?;
+            writer.flush()?;
+            file.sync_all()?;
+            fs::rename(&temp_path, &path)?;
+            Ok(())
+        })();
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err.context(format!("Couldn't save `{}`", path.display())));
+        }
+        // Make the rename itself durable, not every platform allows it
+        if let Ok(dir) = fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+
+        self.saved_revision = self.history.revision();
+        self.saved_line_ending = self.line_ending;
+        self.mixed_line_endings = false;
+        self.history.seal();
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// Line ending of the first line break in `s`, `Lf` if there is none
+    pub fn detect(s: &str) -> LineEnding {
+        match s.find('\n') {
+            Some(i) if s[..i].ends_with('\r') => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+impl Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::Crlf => write!(f, "CRLF"),
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(anyhow!("Unknown line ending, expected lf or crlf")),
+        }
+    }
+}
+
+/// File `path` stands for once every symlink is followed, even a dangling one
+/// that saving will create
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    // Bounded in case of a symlink loop
+    for _ in 0..40 {
+        match fs::read_link(&path) {
+            Ok(target) => {
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    path
+}
+
+/// New file in `dir` to write a copy of `file_name` to before renaming it,
+/// created with no more `permissions` than the file it replaces, if any
+fn create_temp_file(
+    dir: &Path,
+    file_name: &str,
+    permissions: Option<&fs::Permissions>,
+) -> Result<(PathBuf, fs::File)> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if let Some(permissions) = permissions {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(permissions.mode());
+    }
+    #[cfg(not(unix))]
+    let _ = permissions;
+    let mut attempt = 0;
+    loop {
+        let temp_path = dir.join(format!(
+            ".{file_name}.rudit-{}-{attempt}.tmp",
+            std::process::id()
+        ));
+        match options.open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1
+            }
+            Err(err) => {
+                return Err(anyhow!(err).context(format!(
+                    "Couldn't create a file in `{}`, is the directory writable ?",
+                    dir.display()
+                )))
+            }
+        }
+    }
+}
+
+/// Gives `file` the owner and group of the file it replaces, as far as the
+/// user is allowed to
+#[cfg(unix)]
+fn preserve_owner(file: &fs::File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+    if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = fchown(file, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_file: &fs::File, _metadata: &fs::Metadata) {}
+
+/// Whether `s` has both CRLF line breaks and LF ones without a CR before
+fn mixes_line_endings(s: &str) -> bool {
+    let crlf = s.matches("\r\n").count();
+    crlf > 0 && crlf < s.matches('\n').count()
+}
+
+/// Turns every CRLF line break into `\n`, the only one the rope splits lines
+/// on. Lone CRs are kept as they are.
+fn normalize_line_endings(s: &str) -> Cow<'_, str> {
+    if s.contains("\r\n") {
+        Cow::Owned(s.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+fn grapheme_len(line: &str) -> usize {
+    line.graphemes(true).count()
+}
+
+/// Byte offset of the grapheme at column `x`, or the end of the line if `x` is past it
+pub(crate) fn grapheme_to_byte(line: &str, x: usize) -> usize {
+    line.grapheme_indices(true)
+        .nth(x)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len())
+}
+
+/// Column of the grapheme containing byte offset `index`
+pub(crate) fn byte_to_grapheme(line: &str, index: usize) -> usize {
+    line.grapheme_indices(true)
+        .take_while(|(i, g)| i + g.len() <= index)
+        .count()
+}
+
+const TAB_WIDTH: usize = 4;
+
+/// Display width of grapheme `g` when it starts at display column `col`
+fn grapheme_width(g: &str, col: usize) -> usize {
+    if g == "\t" {
+        TAB_WIDTH - col % TAB_WIDTH
+    } else if g.chars().any(char::is_control) {
+        // Rendered as a replacement character by `render_line`
+        1
+    } else {
+        g.width()
+    }
+}
+
+/// Display column at which the grapheme at column `x` starts
+fn display_col(line: &str, x: usize) -> usize {
+    line.graphemes(true)
+        .take(x)
+        .fold(0, |col, g| col + grapheme_width(g, col))
+}
+
+/// Column of the grapheme covering display column `col`, or the end of the line
+fn display_col_to_grapheme(line: &str, col: usize) -> usize {
+    let mut current_col = 0;
+    for (x, g) in line.graphemes(true).enumerate() {
+        current_col += grapheme_width(g, current_col);
+        if current_col > col {
+            return x;
+        }
+    }
+    grapheme_len(line)
+}
+
+/// Grapheme ranges of the successive screen rows `line` takes when wrapped at
+/// `width` columns, breaking after whitespace where possible. A line filling
+/// its last row gets an extra empty one for the cursor to sit on.
+fn wrap_line(line: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut rows = vec![];
+    // Display column at which each grapheme starts
+    let mut cols = vec![];
+    let mut col = 0;
+    let mut row_start = 0;
+    let mut break_after = None;
+    for (x, g) in line.graphemes(true).enumerate() {
+        cols.push(col);
+        col += grapheme_width(g, col);
+        while x > row_start && col - cols[row_start] > width {
+            let end = break_after.filter(|&b| b > row_start).unwrap_or(x);
+            rows.push(row_start..end);
+            row_start = end;
+            break_after = None;
+        }
+        if g.chars().all(char::is_whitespace) {
+            break_after = Some(x + 1);
+        }
+    }
+    let len = cols.len();
+    rows.push(row_start..len);
+    if row_start < len && col - cols[row_start] >= width {
+        rows.push(len..len);
+    }
+    rows
+}
+
+/// Index of the row in `rows` holding the cursor when it is at column `x`
+fn row_index(rows: &[Range<usize>], x: usize) -> usize {
+    rows.iter()
+        .position(|row| x < row.end)
+        .unwrap_or(rows.len() - 1)
+}
+
+/// Graphemes `x_range` of `line` as they should be printed, so that each of
+/// them takes exactly `grapheme_width` cells on the terminal, along with the
+/// display column of the first one
+fn render_graphemes(line: &str, x_range: Range<usize>) -> (usize, String) {
+    let mut rendered = String::new();
+    let mut col = 0;
+    let mut start_col = None;
+    for (x, g) in line.graphemes(true).enumerate() {
+        if x >= x_range.end {
+            break;
+        }
+        let width = grapheme_width(g, col);
+        if x < x_range.start {
+            col += width;
+            continue;
+        }
+        start_col.get_or_insert(col);
+        if g == "\t" {
+            rendered.extend(std::iter::repeat_n(' ', width));
+        } else if g.chars().any(char::is_control) {
+            rendered.push(char::REPLACEMENT_CHARACTER);
+        } else {
+            rendered.push_str(g);
+        }
+        col += width;
+    }
+    (start_col.unwrap_or(col), rendered)
+}
+
+/// Part of the rendered `text` starting at display column `start_col` that lies
+/// within `cols`, with the column it starts at. Wide characters cut by the
+/// edges are replaced by spaces.
+fn clip_columns(start_col: usize, text: &str, cols: Range<usize>) -> (usize, String) {
+    let mut clipped = String::new();
+    let mut clipped_start = None;
+    let mut col = start_col;
+    for g in text.graphemes(true) {
+        let (g_start, g_end) = (col, col + g.width());
+        col = g_end;
+        if g_start >= cols.end {
+            break;
+        }
+        if g_start < cols.start && g_end <= cols.start {
+            continue;
+        }
+        let visible_start = g_start.max(cols.start);
+        let visible_end = g_end.min(cols.end);
+        clipped_start.get_or_insert(visible_start);
+        if visible_start == g_start && visible_end == g_end {
+            clipped.push_str(g);
+        } else {
+            clipped.extend(std::iter::repeat_n(' ', visible_end - visible_start));
+        }
+    }
+    (
+        clipped_start.unwrap_or(start_col.clamp(cols.start, cols.end)),
+        clipped,
+    )
+}
+
+/// Replaces the first and/or last cell of the rendered `text` with overflow
+/// indicators
+fn mark_overflow(text: &str, left: bool, right: bool) -> String {
+    let mut graphemes: Vec<&str> = text.graphemes(true).collect();
+    let mut prefix = String::new();
+    let mut suffix = String::new();
+    if left {
+        let mut removed = 0;
+        while removed == 0 && !graphemes.is_empty() {
+            removed += graphemes.remove(0).width();
+        }
+        prefix.push('<');
+        prefix.extend(std::iter::repeat_n(' ', removed.saturating_sub(1)));
+    }
+    if right {
+        let mut removed = 0;
+        while removed == 0 {
+            let Some(g) = graphemes.pop() else {
+                break;
+            };
+            removed += g.width();
+        }
+        if removed > 0 {
+            suffix.extend(std::iter::repeat_n(' ', removed - 1));
+            suffix.push('>');
+        }
+    }
+    prefix + &graphemes.concat() + &suffix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_line_keeps_short_lines_on_one_row() {
+        assert_eq!(wrap_line("", 10), vec![0..0]);
+        assert_eq!(wrap_line("abc", 10), vec![0..3]);
+    }
+
+    #[test]
+    fn wrap_line_adds_a_row_after_a_full_one() {
+        assert_eq!(wrap_line("abcd", 4), vec![0..4, 4..4]);
+        assert_eq!(wrap_line("abcdefgh", 4), vec![0..4, 4..8, 8..8]);
+    }
+
+    #[test]
+    fn wrap_line_breaks_after_whitespace() {
+        assert_eq!(wrap_line("hello world foo", 8), vec![0..6, 6..12, 12..15]);
+    }
+
+    #[test]
+    fn wrap_line_breaks_words_longer_than_a_row() {
+        assert_eq!(wrap_line("abcdefghij", 4), vec![0..4, 4..8, 8..10]);
+        assert_eq!(
+            wrap_line("a abcdefghij", 4),
+            vec![0..2, 2..6, 6..10, 10..12]
+        );
+    }
+
+    #[test]
+    fn wrap_line_counts_display_columns() {
+        // Each character takes two columns, the third doesn't fit
+        assert_eq!(wrap_line("日本語", 5), vec![0..2, 2..3]);
+        // A combining mark takes no room of its own
+        assert_eq!(wrap_line("e\u{301}e\u{301}e", 2), vec![0..2, 2..3]);
+    }
+
+    #[test]
+    fn wrap_line_needs_at_least_one_column() {
+        assert_eq!(wrap_line("ab", 0), vec![0..1, 1..2, 2..2]);
+    }
+
+    #[test]
+    fn load_from_str_of_empty_text_is_like_a_new_buffer() {
+        let loaded = Buffer::load_from_str("");
+        assert!(loaded.has_trailing_newline());
+        assert_eq!(loaded.get_contents(), Buffer::new().get_contents());
+        assert!(!Buffer::load_from_str("a").has_trailing_newline());
+    }
+}
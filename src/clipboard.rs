@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use crossterm::queue;
+use serde::{Deserialize, Serialize};
+
+/// Register used by the copy, cut and paste actions unless another one is
+/// picked, the only one forwarded to the system clipboard
+pub const DEFAULT_REGISTER: char = '"';
+
+/// How copied text is forwarded to the system clipboard
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ClipboardBridge {
+    /// Copied text stays inside rudit
+    #[default]
+    None,
+    /// OSC 52 escape sequence, handled by the terminal itself so it also
+    /// works over SSH
+    Osc52,
+    /// Command receiving the copied text on its stdin, e.g. `wl-copy` or
+    /// `xclip -selection clipboard`
+    Command(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    /// Text last copied or cut to each register
+    registers: HashMap<char, String>,
+    /// Text waiting to be sent to the terminal through OSC 52
+    pending_osc52: Option<String>,
+}
+
+impl Clipboard {
+    pub fn new() -> Clipboard {
+        Clipboard::default()
+    }
+
+    pub fn get(&self, register: char) -> Option<&str> {
+        self.registers.get(&register).map(|text| text.as_str())
+    }
+
+    /// Stores `text` in `register`, text stored in the default register is also
+    /// forwarded to the system clipboard through `bridge`. The text is kept
+    /// for pasting inside rudit even if forwarding fails.
+    pub fn set(&mut self, register: char, text: String, bridge: &ClipboardBridge) -> Result<()> {
+        let text = self.registers.entry(register).insert_entry(text).into_mut();
+        if register != DEFAULT_REGISTER {
+            return Ok(());
+        }
+        match bridge {
+            ClipboardBridge::None => (),
+            ClipboardBridge::Osc52 => self.pending_osc52 = Some(text.clone()),
+            ClipboardBridge::Command(command) => run_copy_command(command, text)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the escape sequences still waiting for the terminal
+    pub fn flush<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        if let Some(text) = self.pending_osc52.take() {
+            queue!(writer, SetSystemClipboard(&text))?;
+        }
+        Ok(())
+    }
+}
+
+fn run_copy_command(command: &str, text: &str) -> Result<()> {
+    let args = comma::parse_command(command).context("Invalid clipboard command")?;
+    let (program, args) = args.split_first().context("Empty clipboard command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Couldn't run clipboard command `{command}`"))?;
+    // Dropping stdin closes it, so that the command sees the end of the text
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin
+            .write_all(text.as_bytes())
+            .map_err(anyhow::Error::from),
+        None => Err(anyhow!("No stdin for clipboard command")),
+    };
+    // Even if writing failed, so that the command doesn't linger
+    let status = child.wait()?;
+    written.with_context(|| format!("Couldn't write to clipboard command `{command}`"))?;
+    if !status.success() {
+        bail!("Clipboard command `{command}` failed ({status})");
+    }
+    Ok(())
+}
+
+/// Sets the system clipboard through the OSC 52 escape sequence
+struct SetSystemClipboard<'a>(&'a str);
+
+impl crossterm::Command for SetSystemClipboard<'_> {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        write!(f, "\x1b]52;c;{}\x07", BASE64_STANDARD.encode(self.0))
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "OSC 52 needs a terminal supporting ANSI sequences",
+        ))
+    }
+}
@@ -3,19 +3,174 @@ use std::{fmt::Display, str::FromStr};
 use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
-pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+/// Names of the 16 ANSI colours, in the order of their indices
+const ANSI_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+/// A terminal colour, written as `#rgb`, `#rrggbb`, `rgb(r, g, b)`, an ANSI
+/// colour name, a 256-colour index or `default`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Color {
+    Rgb {
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    /// Entry of the terminal's 256-colour palette, the first 16 being the
+    /// ANSI colours
+    Indexed(u8),
+    /// Whatever colour the terminal uses by default
+    Default,
+}
+
+impl Color {
+    /// Closest colour the terminal can show with `support`, which must not be
+    /// `ColorSupport::Auto`
+    pub fn quantize(self, support: ColorSupport) -> Color {
+        match (support, self) {
+            (ColorSupport::None, _) => Color::Default,
+            (ColorSupport::Auto | ColorSupport::TrueColor, color) | (_, color @ Color::Default) => {
+                color
+            }
+            (ColorSupport::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::Indexed(nearest_256((r, g, b)))
+            }
+            (ColorSupport::Ansi256, color) => color,
+            (ColorSupport::Ansi16, Color::Indexed(index)) if index < 16 => self,
+            (ColorSupport::Ansi16, Color::Indexed(index)) => {
+                Color::Indexed(nearest_16(indexed_rgb(index)))
+            }
+            (ColorSupport::Ansi16, Color::Rgb { r, g, b }) => Color::Indexed(nearest_16((r, g, b))),
+        }
+    }
+}
+
+/// Colours a terminal is able to show
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColorSupport {
+    /// Detected from the environment
+    #[default]
+    Auto,
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    /// Only the terminal's default colours, as asked by `NO_COLOR`
+    None,
+}
+
+impl ColorSupport {
+    /// Support detected from the `NO_COLOR`, `COLORTERM` and `TERM` variables
+    pub fn detect() -> ColorSupport {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        if !var("NO_COLOR").is_empty() {
+            return ColorSupport::None;
+        }
+        if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") {
+            return ColorSupport::TrueColor;
+        }
+        match var("TERM").as_str() {
+            // Windows terminals don't set TERM but all handle true colour
+            "" => ColorSupport::TrueColor,
+            "dumb" => ColorSupport::None,
+            term if term.contains("direct") => ColorSupport::TrueColor,
+            term if term.contains("256") => ColorSupport::Ansi256,
+            _ => ColorSupport::Ansi16,
+        }
+    }
+
+    /// This support, or the one detected from the environment if it is `Auto`
+    pub fn resolve(self) -> ColorSupport {
+        match self {
+            ColorSupport::Auto => ColorSupport::detect(),
+            support => support,
+        }
+    }
+}
+
+/// Levels of each channel in the 6x6x6 cube of the 256-colour palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Usual values of the 16 ANSI colours, as used by xterm
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).unsigned_abs().pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// RGB value of an entry of the 256-colour palette
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..16 => ANSI_RGB[index as usize],
+        16..232 => {
+            let i = (index - 16) as usize;
+            (
+                CUBE_LEVELS[i / 36],
+                CUBE_LEVELS[i / 6 % 6],
+                CUBE_LEVELS[i % 6],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+/// Closest entry of the colour cube or grey ramp of the 256-colour palette,
+/// the first 16 entries are left out as terminals often redefine them
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&index| distance(rgb, indexed_rgb(index)))
+        .unwrap_or_default()
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&index| distance(rgb, indexed_rgb(index)))
+        .unwrap_or_default()
 }
 
 impl From<Color> for crossterm::style::Color {
     fn from(value: Color) -> Self {
-        crossterm::style::Color::Rgb {
-            r: value.r,
-            g: value.g,
-            b: value.b,
+        match value {
+            Color::Rgb { r, g, b } => crossterm::style::Color::Rgb { r, g, b },
+            Color::Indexed(index) => crossterm::style::Color::AnsiValue(index),
+            Color::Default => crossterm::style::Color::Reset,
         }
     }
 }
@@ -24,27 +179,74 @@ impl FromStr for Color {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.starts_with("#") {
-            let r = u8::from_str_radix(s.get(1..3).context("Invalid syntax for Color : red")?, 16)?;
-            let g =
-                u8::from_str_radix(s.get(3..5).context("Invalid syntax for Color : green")?, 16)?;
-            let b =
-                u8::from_str_radix(s.get(5..7).context("Invalid syntax for Color : blue")?, 16)?;
-            Ok(Color { r, g, b })
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            parse_hex(hex)
+                .with_context(|| format!("Invalid colour `{s}`, expected #rgb or #rrggbb"))
+        } else if let Some(channels) = s.strip_prefix("rgb(").and_then(|s| s.strip_suffix(')')) {
+            let channels = channels
+                .split(',')
+                .map(|c| c.trim().parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid colour `{s}`, channels go from 0 to 255"))?;
+            match channels[..] {
+                [r, g, b] => Ok(Color::Rgb { r, g, b }),
+                _ => Err(anyhow!("Invalid colour `{s}`, expected rgb(r, g, b)")),
+            }
+        } else if let Some(index) = ANSI_NAMES.iter().position(|name| *name == s) {
+            Ok(Color::Indexed(index as u8))
+        } else if s == "default" {
+            Ok(Color::Default)
+        } else if let Ok(index) = s.parse::<u8>() {
+            Ok(Color::Indexed(index))
         } else {
-            Err(anyhow!("Invalid syntax for Color"))
+            Err(anyhow!(
+                "Invalid colour `{s}`, expected #rgb, #rrggbb, rgb(r, g, b), an ANSI colour name, a 256-colour index or default"
+            ))
         }
     }
 }
 
-impl From<&str> for Color {
-    fn from(value: &str) -> Self {
-        Color::from_str(value).unwrap_or_default()
+fn parse_hex(hex: &str) -> Option<Color> {
+    let channel = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+    match hex.len() {
+        3 => Some(Color::Rgb {
+            r: channel(0..1)? * 17,
+            g: channel(1..2)? * 17,
+            b: channel(2..3)? * 17,
+        }),
+        6 => Some(Color::Rgb {
+            r: channel(0..2)?,
+            g: channel(2..4)?,
+            b: channel(4..6)?,
+        }),
+        _ => None,
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Color::from_str(&value)
+    }
+}
+
+impl From<Color> for String {
+    fn from(value: Color) -> Self {
+        value.to_string()
     }
 }
 
 impl Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        match self {
+            Color::Rgb { r, g, b } => write!(f, "#{r:02x}{g:02x}{b:02x}"),
+            Color::Indexed(index) => match ANSI_NAMES.get(*index as usize) {
+                Some(name) => write!(f, "{name}"),
+                None => write!(f, "{index}"),
+            },
+            Color::Default => write!(f, "default"),
+        }
     }
 }
@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf};
+
+use unicode_width::UnicodeWidthStr;
+
+/// How the word before the cursor in the command zone can be completed
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    /// Text to insert at the cursor, shared by every candidate
+    pub insert: String,
+    /// Candidates left to choose from, empty unless there are several
+    pub candidates: Vec<String>,
+}
+
+/// Completes the first word of `line`, the text typed before the cursor, with
+/// one of `commands`, and the argument of a command in `path_commands` with
+/// the files and directories it may name
+pub fn complete(line: &str, commands: &[&str], path_commands: &[&str]) -> Completion {
+    let (word, candidates, end) = match line.trim_start().split_once(char::is_whitespace) {
+        None => {
+            let word = line.trim_start();
+            let candidates = commands
+                .iter()
+                .filter(|command| command.starts_with(word))
+                .map(|command| command.to_string())
+                .collect();
+            (word, candidates, " ")
+        }
+        Some((command, _)) if path_commands.contains(&command) => {
+            let argument = line.rsplit(char::is_whitespace).next().unwrap_or_default();
+            let word = argument.rsplit('/').next().unwrap_or_default();
+            let directory = &argument[..argument.len() - word.len()];
+            (word, path_candidates(directory, word), "")
+        }
+        Some(_) => return Completion::default(),
+    };
+
+    match candidates.as_slice() {
+        [] => Completion::default(),
+        // Directories end with a slash, so that completion goes on inside
+        [candidate] if candidate.ends_with('/') => Completion {
+            insert: candidate[word.len()..].to_string(),
+            candidates: vec![],
+        },
+        [candidate] => Completion {
+            insert: format!("{}{end}", &candidate[word.len()..]),
+            candidates: vec![],
+        },
+        _ => Completion {
+            insert: common_prefix(&candidates)[word.len()..].to_string(),
+            candidates,
+        },
+    }
+}
+
+/// Names of the entries of `directory` starting with `prefix`, sorted, with a
+/// slash after those of directories. Hidden ones are left out unless `prefix`
+/// starts with a dot.
+fn path_candidates(directory: &str, prefix: &str) -> Vec<String> {
+    let path = match directory.strip_prefix("~/") {
+        Some(rest) => home::home_dir().unwrap_or_default().join(rest),
+        None if directory.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(directory),
+    };
+    let Ok(entries) = fs::read_dir(path) else {
+        return vec![];
+    };
+    let mut candidates: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Follows symbolic links to tell whether they lead to directories
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_dir());
+            Some(if is_dir { format!("{name}/") } else { name })
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Longest text every candidate starts with
+fn common_prefix(candidates: &[String]) -> &str {
+    let first = &candidates[0];
+    let len = candidates[1..].iter().fold(first.len(), |len, candidate| {
+        first[..len]
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(candidate.len()), |((i, _), _)| i)
+    });
+    &first[..len]
+}
+
+/// Candidates laid out in as many columns as fit in `width`, filled top to
+/// bottom
+pub fn columns(candidates: &[String], width: usize) -> String {
+    let column_width = candidates
+        .iter()
+        .map(|c| c.width())
+        .max()
+        .unwrap_or_default()
+        + 2;
+    let column_count = (width / column_width).max(1);
+    let rows = candidates.len().div_ceil(column_count);
+    (0..rows)
+        .map(|row| {
+            candidates
+                .iter()
+                .skip(row)
+                .step_by(rows)
+                .map(|candidate| format!("{candidate:column_width$}"))
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
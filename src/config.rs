@@ -1,52 +1,130 @@
-use crate::{color::Color, editor::EditorAction};
-use crossterm::style;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::{collections::HashMap, str::FromStr};
+use crate::{
+    clipboard::ClipboardBridge,
+    color::ColorSupport,
+    editor::{EditorAction, LineNumbers},
+    status::StatusBarTemplate,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
-    pub color_edit_zone: ColorPair,
-    pub color_status_bar: ColorPair,
-    pub color_command_zone: ColorPair,
+    /// Name of a theme shipped with rudit, or of a theme file
+    pub theme: String,
+    /// Colours the terminal can show, detected from the environment by default
+    pub color_support: ColorSupport,
     pub edit_keybindings: HashMap<String, EditorAction>,
     pub command_keybindings: HashMap<String, EditorAction>,
     pub use_mouse: bool,
     pub use_paste: bool,
+    pub clipboard_bridge: ClipboardBridge,
+    pub search_case_sensitive: bool,
+    pub search_wrap_around: bool,
+    pub show_overflow_indicators: bool,
+    pub wrap: bool,
+    pub line_numbers: LineNumbers,
+    pub status_bar: StatusBarTemplate,
+    /// Keep unsaved changes in a swap file next to the document
+    pub swap_file: bool,
+    /// Minimum time between two writes of the swap file
+    pub swap_interval_ms: u64,
+    /// Keys of the user config rudit doesn't know, which are ignored
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
-pub struct ColorPair {
-    #[serde(deserialize_with = "deserialize_color_from_str")]
-    #[serde(serialize_with = "serialize_color_to_str")]
-    bg: Color,
-    #[serde(deserialize_with = "deserialize_color_from_str")]
-    #[serde(serialize_with = "serialize_color_to_str")]
-    fg: Color,
-}
+const DEFAULT_CONFIG: &str = include_str!("rudit.toml");
+/// Tables whose entries replace the default ones whole, so that a key bound
+/// by the user keeps nothing of its default action
+const KEYBINDING_TABLES: [&str; 2] = ["edit_keybindings", "command_keybindings"];
+/// Action a key is bound to in the user config to remove its default binding
+const UNBOUND: &str = "None";
 
-fn deserialize_color_from_str<'de, D>(deserializer: D) -> Result<Color, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Ok(Color::from_str(&String::deserialize(deserializer)?).unwrap_or_default())
-}
+impl Config {
+    /// Parses a user config, keys missing from it keep their default value
+    pub fn from_toml_str(s: &str) -> anyhow::Result<Config> {
+        let mut config: toml::Table = toml::from_str(DEFAULT_CONFIG)?;
+        let mut overrides: toml::Table = toml::from_str(s)?;
+        let unknown_keys = overrides
+            .keys()
+            .filter(|key| !config.contains_key(*key))
+            .cloned()
+            .collect();
+        for name in KEYBINDING_TABLES {
+            if let (Some(toml::Value::Table(defaults)), Some(toml::Value::Table(bindings))) =
+                (config.get_mut(name), overrides.get_mut(name))
+            {
+                defaults.extend(std::mem::take(bindings));
+                // Unbinds the key
+                defaults.retain(|_, action| action.as_str() != Some(UNBOUND));
+            }
+        }
+        merge_tables(&mut config, overrides);
+        Ok(Config {
+            unknown_keys,
+            ..config.try_into()?
+        })
+    }
 
-fn serialize_color_to_str<S>(x: &Color, s: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    s.serialize_str(&format!("{x}"))
+    /// Message about the ignored keys of the user config, pointing the
+    /// `color_*` tables of older versions to themes
+    pub fn warning(&self) -> Option<String> {
+        if self.unknown_keys.is_empty() {
+            return None;
+        }
+        let keys = self
+            .unknown_keys
+            .iter()
+            .map(|key| format!("`{key}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(
+            if self
+                .unknown_keys
+                .iter()
+                .any(|key| key.starts_with("color_"))
+            {
+                format!("Ignored config keys {keys}, colours are now set by the `theme` key")
+            } else {
+                format!("Ignored config keys {keys}")
+            },
+        )
+    }
 }
 
-impl From<ColorPair> for style::Colors {
-    fn from(value: ColorPair) -> Self {
-        style::Colors::new(value.fg.into(), value.bg.into())
+pub(crate) fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_tables(base_table, table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let default_config = include_str!("rudit.toml");
-        toml::from_str(&default_config).unwrap()
+        toml::from_str(DEFAULT_CONFIG).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let config =
+            Config::from_toml_str("wrap = true\n[color_edit_zone]\nbg = \"#000000\"").unwrap();
+        assert!(config.wrap);
+        assert_eq!(config.unknown_keys, ["color_edit_zone"]);
+        assert!(config.warning().unwrap().contains("`theme`"));
+        assert_eq!(
+            Config::from_toml_str("wrap = true").unwrap().warning(),
+            None
+        );
     }
 }
@@ -7,12 +7,37 @@ use crossterm::{
 use serde::{Deserialize, Serialize};
 use std::{
     fs,
-    io::{Stdout, Write},
+    io::{self, Stdout, Write},
     path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{buffer::Buffer, config::Config, pos::Pos};
+use crate::{
+    buffer::{Buffer, LineEnding, View},
+    clipboard::{Clipboard, DEFAULT_REGISTER},
+    color::ColorSupport,
+    completion,
+    config::Config,
+    finder::Finder,
+    highlight::Highlighter,
+    pos::Pos,
+    search::{Search, SubstituteScope, Substitution},
+    status::truncate_end,
+    swap::{Recovery, SwapFile, SwapWriter},
+    tab_bar::TabBar,
+    theme::{SetStyle, Theme},
+    window::{Layout, SplitDirection, Window},
+};
+
+const QUIT_PROMPT: &str = "Unsaved changes, (s)ave, (d)iscard or (c)ancel ? ";
+const RECOVER_PROMPT: &str = "Swap file found, (r)estore, (v)iew diff, (d)elete or (q)uit ? ";
+/// How often the file finder shows the files found while indexing
+const FINDER_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+/// Narrowest edit zone the file finder shows a preview in
+const FINDER_PREVIEW_MIN_WIDTH: usize = 60;
 
 #[derive(Debug, Clone)]
 pub struct Editor {
@@ -20,10 +45,52 @@ pub struct Editor {
     edit_buffer: Buffer,
     state: EditorState,
     config: Config,
+    theme: Theme,
     filename: Option<PathBuf>,
     last_keypress: String,
     need_full_clear: bool,
     command_buffer: Buffer,
+    clipboard: Clipboard,
+    /// Register the next copy, cut or paste uses, the default one again after
+    register: char,
+    search_query: String,
+    /// Cursor position the incremental search starts from
+    search_origin: Pos,
+    /// Substitution waiting for each match to be confirmed
+    substitution: Option<Substitution>,
+    status_message: Option<String>,
+    /// Carry on closing once the document is saved through `save_as`
+    close_after_save: bool,
+    /// What is closed once the unsaved changes are saved or discarded
+    closing: Closing,
+    /// Windows the edit zone is split into, the focused one showing the
+    /// document being edited
+    windows: Vec<Window>,
+    focused: usize,
+    layout: Layout,
+    edit_zone_size: Pos,
+    /// Borders between windows side by side, as their top and height
+    borders: Vec<(Pos, usize)>,
+    /// Whether the edit zone leaves the top row to the tab bar
+    tab_bar_shown: bool,
+    highlighter: Option<Highlighter>,
+    swap: SwapFile,
+    swap_writer: SwapWriter,
+    /// Every open document, in the order they were opened. The one being
+    /// edited lives in `edit_buffer`, `filename`, `highlighter` and `swap`,
+    /// its own entry at index `current` being left empty meanwhile.
+    documents: Vec<Document>,
+    current: usize,
+    /// Swap file found when opening the document, waiting for the user to
+    /// decide what to do with it
+    recovery: Option<Recovery>,
+    /// Document set aside while the diff of the swap file is shown instead
+    hidden_document: Option<Buffer>,
+    /// Command being typed, set aside while completion candidates are listed
+    /// below it
+    hidden_command: Option<Buffer>,
+    /// File picker of `FinderMode`
+    finder: Option<Finder>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -31,9 +98,26 @@ pub enum EditorState {
     Init,
     EditMode,
     CommandMode,
+    SearchMode,
+    ConfirmMode,
+    /// Asking what to do with unsaved changes before quitting
+    QuitMode,
+    /// Asking what to do with the swap file found next to the document
+    RecoverMode,
+    /// Picking one of the open documents in the command zone
+    BufferListMode,
+    /// Picking a file of the working directory to open, by typing some of
+    /// its path in the command zone
+    FinderMode,
     Close,
 }
 
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Editor {
     pub fn new() -> Editor {
         Editor {
@@ -41,14 +125,50 @@ impl Editor {
             edit_buffer: Buffer::new(),
             state: EditorState::Init,
             config: Config::default(),
+            theme: Theme::default().for_color_support(ColorSupport::detect()),
             filename: None,
             last_keypress: String::new(),
             need_full_clear: false,
             command_buffer: Buffer::new(),
+            clipboard: Clipboard::new(),
+            register: DEFAULT_REGISTER,
+            search_query: String::new(),
+            search_origin: Pos::default(),
+            substitution: None,
+            status_message: None,
+            close_after_save: false,
+            closing: Closing::Editor,
+            windows: vec![Window::default()],
+            focused: 0,
+            layout: Layout::Window(0),
+            edit_zone_size: Pos::default(),
+            borders: vec![],
+            tab_bar_shown: false,
+            highlighter: None,
+            swap: SwapFile::default(),
+            swap_writer: SwapWriter::new(),
+            documents: vec![Document::default()],
+            current: 0,
+            recovery: None,
+            hidden_document: None,
+            hidden_command: None,
+            finder: None,
         }
     }
 
     fn set_state(&mut self, state: EditorState) {
+        if state == EditorState::Close {
+            // Quitting either saved or discarded the changes
+            self.swap.remove(&self.swap_writer);
+            for document in self.documents.iter_mut() {
+                document.swap.remove(&self.swap_writer);
+            }
+            self.swap_writer.flush();
+        }
+        if state != EditorState::CommandMode {
+            // Whatever left the command zone wasn't the save asked for
+            self.close_after_save = false;
+        }
         self.state = state;
         self.update_layout(self.window_size);
     }
@@ -62,36 +182,358 @@ impl Editor {
         P: AsRef<Path>,
     {
         let file_content = fs::read_to_string(path)?;
-        self.config = toml::from_str(&file_content)?;
+        self.config = Config::from_toml_str(&file_content)?;
+        self.status_message = self.config.warning();
+        self.theme =
+            Theme::load(&self.config.theme)?.for_color_support(self.config.color_support.resolve());
         Ok(())
     }
 
     pub fn set_document<P>(&mut self, path: P) -> Result<()>
     where
-        P: AsRef<Path> + Clone,
+        P: AsRef<Path>,
         PathBuf: From<P>,
     {
-        self.edit_buffer = match Buffer::load_from_file(path.clone()) {
-            Ok(buffer) => buffer,
-            Err(_) => Buffer::new(),
+        let buffer = Editor::read_document(path.as_ref())?;
+        self.edit_loaded_document(path.into(), buffer);
+        Ok(())
+    }
+
+    /// Text of the document at `path`, empty if there is no such file yet as
+    /// saving will create it
+    fn read_document(path: &Path) -> Result<Buffer> {
+        match Buffer::load_from_file(path) {
+            Ok(buffer) => Ok(buffer),
+            Err(err)
+                if err
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|err| err.kind() == io::ErrorKind::NotFound) =>
+            {
+                Ok(Buffer::default())
+            }
+            Err(err) => Err(err.context(format!("Couldn't open `{}`", path.display()))),
+        }
+    }
+
+    /// Edits `buffer`, read from `path`, in place of the current document
+    fn edit_loaded_document(&mut self, path: PathBuf, buffer: Buffer) {
+        self.swap.remove(&self.swap_writer);
+        if buffer.has_mixed_line_endings() {
+            self.status_message = Some(format!(
+                "Mixed line endings, saving will turn them all into {} (see set_line_ending)",
+                buffer.get_line_ending()
+            ));
+        }
+        self.edit_buffer = buffer;
+        self.set_filename(Some(path));
+        // The new buffer has yet to be laid out in its window
+        self.update_layout(self.window_size);
+        self.recovery = self
+            .filename
+            .as_deref()
+            .and_then(|filename| Recovery::find(filename, &self.edit_buffer));
+        // Before the first display, `init` is the one entering the mode
+        if self.recovery.is_some() && self.state != EditorState::Init {
+            self.set_state(EditorState::RecoverMode);
+        }
+    }
+
+    /// Longest time to wait for an event before updating the swap file
+    pub fn swap_interval(&self) -> Duration {
+        Duration::from_millis(self.config.swap_interval_ms)
+    }
+
+    /// Longest time to wait for an event before displaying again, shorter
+    /// while the file finder shows the files being indexed
+    pub fn event_timeout(&self) -> Duration {
+        match &self.finder {
+            Some(finder) if finder.is_indexing() => FINDER_REFRESH_INTERVAL,
+            _ => self.swap_interval(),
+        }
+    }
+
+    /// Writes the unsaved changes to the swap file of the document if they
+    /// weren't for `swap_interval_ms`, or removes it once they are saved
+    pub fn update_swap_file(&mut self) {
+        if let Some(err) = self.swap_writer.take_error() {
+            self.status_message = Some(format!("Error : {err}"));
+        }
+        if self.state == EditorState::RecoverMode {
+            return;
+        }
+        match &self.filename {
+            Some(filename) if self.config.swap_file => self.swap.update(
+                &self.swap_writer,
+                filename,
+                &self.edit_buffer,
+                self.swap_interval(),
+            ),
+            _ => self.swap.remove(&self.swap_writer),
+        }
+    }
+
+    /// Changes the name the document is saved under, along with the grammar
+    /// it is highlighted with
+    /// Register for the clipboard action being run, the next one uses the
+    /// default register again
+    fn take_register(&mut self) -> char {
+        std::mem::replace(&mut self.register, DEFAULT_REGISTER)
+    }
+
+    fn set_filename(&mut self, filename: Option<PathBuf>) {
+        self.highlighter = filename.as_deref().and_then(Highlighter::for_path);
+        self.filename = filename;
+    }
+
+    /// Edits the document at `index`, putting the current one aside
+    fn switch_document(&mut self, index: usize) {
+        if index == self.current || index >= self.documents.len() {
+            return;
+        }
+        self.documents[self.current] = Document {
+            buffer: std::mem::take(&mut self.edit_buffer),
+            filename: self.filename.take(),
+            highlighter: self.highlighter.take(),
+            swap: std::mem::take(&mut self.swap),
+        };
+        self.load_document(index);
+    }
+
+    /// Moves the document at `index` out of the list and into the edit zone,
+    /// replacing whatever was there
+    fn load_document(&mut self, index: usize) {
+        let document = std::mem::take(&mut self.documents[index]);
+        self.edit_buffer = document.buffer;
+        self.filename = document.filename;
+        self.highlighter = document.highlighter;
+        self.swap = document.swap;
+        self.current = index;
+        self.windows[self.focused].document = index;
+        self.update_layout(self.window_size);
+    }
+
+    /// Edits the document at `path`, switching to it if it is already open.
+    /// The current document is replaced if it is an empty unnamed one.
+    fn open_document(&mut self, path: &str) -> Result<()> {
+        let path = PathBuf::from(path);
+        let same_file = |filename: &Path| {
+            filename == path
+                || fs::canonicalize(filename)
+                    .is_ok_and(|filename| fs::canonicalize(&path).is_ok_and(|p| p == filename))
         };
-        self.filename = Some(path.into());
+        let open = self
+            .document_states()
+            .iter()
+            .position(|(filename, _)| filename.is_some_and(same_file));
+        if let Some(index) = open {
+            self.switch_document(index);
+            return Ok(());
+        }
+        // Before making room for it, so that nothing changes if it can't be read
+        let buffer = Editor::read_document(&path)?;
+        let untouched = self.filename.is_none() && !self.edit_buffer.is_modified();
+        if !untouched {
+            self.documents.push(Document::default());
+            self.switch_document(self.documents.len() - 1);
+        }
+        self.edit_loaded_document(path, buffer);
         Ok(())
     }
 
+    /// Closes the document being edited, asking first whether to save its
+    /// unsaved changes
+    fn close_document(&mut self) {
+        if self.edit_buffer.is_modified() {
+            self.closing = Closing::Document;
+            self.set_state(EditorState::QuitMode);
+        } else {
+            self.remove_document();
+        }
+    }
+
+    /// Closes the document being edited, dropping its unsaved changes, and
+    /// edits the next one, or an empty document if it was the last one
+    fn remove_document(&mut self) {
+        self.swap.remove(&self.swap_writer);
+        let removed = self.current;
+        if self.documents.len() == 1 {
+            self.documents[0] = Document::default();
+        } else {
+            self.documents.remove(removed);
+        }
+        let next = removed.min(self.documents.len() - 1);
+        for window in self.windows.iter_mut() {
+            if window.document == removed {
+                window.document = next;
+                window.view = View::default();
+            } else if window.document > removed {
+                window.document -= 1;
+            }
+        }
+        self.load_document(next);
+    }
+
+    /// Buffer of the document at `index`
+    fn document_buffer(&self, index: usize) -> &Buffer {
+        if index == self.current {
+            &self.edit_buffer
+        } else {
+            &self.documents[index].buffer
+        }
+    }
+
+    fn document_buffer_mut(&mut self, index: usize) -> &mut Buffer {
+        if index == self.current {
+            &mut self.edit_buffer
+        } else {
+            &mut self.documents[index].buffer
+        }
+    }
+
+    /// Gives the focus to the window at `index`, which the current one keeps
+    /// its view for
+    fn focus_window(&mut self, index: usize) {
+        if index == self.focused || index >= self.windows.len() {
+            return;
+        }
+        self.windows[self.focused].view = self.edit_buffer.view();
+        self.enter_window(index);
+    }
+
+    /// Edits the document of the window at `index` from its view
+    fn enter_window(&mut self, index: usize) {
+        self.focused = index;
+        let window = self.windows[index].clone();
+        self.switch_document(window.document);
+        self.update_layout(self.window_size);
+        self.edit_buffer.set_view(window.view);
+    }
+
+    /// Splits the focused window in two showing the same document, the new
+    /// one getting the focus
+    fn split_window(&mut self, direction: SplitDirection) {
+        let window = Window {
+            view: self.edit_buffer.view(),
+            ..self.windows[self.focused].clone()
+        };
+        self.windows.push(window);
+        let new_window = self.windows.len() - 1;
+        self.layout.split(self.focused, new_window, direction);
+        self.focus_window(new_window);
+    }
+
+    /// Closes the focused window, leaving its document open
+    fn close_window(&mut self) -> Result<()> {
+        let closed = self.focused;
+        if !self.layout.remove(closed) {
+            return Err(anyhow!("Can't close the last window"));
+        }
+        self.windows.remove(closed);
+        self.enter_window(closed.min(self.windows.len() - 1));
+        Ok(())
+    }
+
+    /// Focuses the window after or before the focused one, from top left to
+    /// bottom right
+    fn cycle_window(&mut self, forward: bool) {
+        let order = self.layout.windows();
+        let position = order
+            .iter()
+            .position(|index| *index == self.focused)
+            .unwrap_or_default();
+        let next = if forward {
+            (position + 1) % order.len()
+        } else {
+            (position + order.len() - 1) % order.len()
+        };
+        self.focus_window(order[next]);
+    }
+
+    /// File name and unsaved changes of every open document, in order
+    fn document_states(&self) -> Vec<(Option<&Path>, bool)> {
+        self.documents
+            .iter()
+            .enumerate()
+            .map(|(index, document)| {
+                if index == self.current {
+                    (self.filename.as_deref(), self.edit_buffer.is_modified())
+                } else {
+                    (document.filename.as_deref(), document.buffer.is_modified())
+                }
+            })
+            .collect()
+    }
+
+    /// Index of the document whose number or file name is `name`, or whose
+    /// path contains it if no other does
+    fn find_document(&self, name: &str) -> Result<usize> {
+        let paths: Vec<_> = self
+            .document_states()
+            .into_iter()
+            .map(|(filename, _)| filename)
+            .collect();
+        if let Ok(number) = name.parse::<usize>() {
+            return number
+                .checked_sub(1)
+                .filter(|index| *index < paths.len())
+                .with_context(|| format!("No buffer {number}"));
+        }
+        if let Some(index) = paths
+            .iter()
+            .position(|path| path.and_then(Path::file_name).is_some_and(|f| f == name))
+        {
+            return Ok(index);
+        }
+        let mut matching = paths
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| path.is_some_and(|path| path.to_string_lossy().contains(name)));
+        match (matching.next(), matching.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (Some(_), Some(_)) => Err(anyhow!("Several buffers match `{name}`")),
+            (None, _) => Err(anyhow!("No buffer matches `{name}`")),
+        }
+    }
+
+    /// Lists the open documents in the command zone to pick one from
+    fn list_buffers(&mut self) {
+        let list: Vec<String> = self
+            .document_states()
+            .iter()
+            .enumerate()
+            .map(|(index, (filename, modified))| {
+                format!(
+                    "{:>3}  {}{}",
+                    index + 1,
+                    filename.map_or("[No Name]".into(), |f| f.display().to_string()),
+                    if *modified { " [+]" } else { "" }
+                )
+            })
+            .collect();
+        self.command_buffer = Buffer::load_from_str(&list.join("\n"));
+        self.set_state(EditorState::BufferListMode);
+        self.command_buffer.move_cursor(Pos::new(0, self.current));
+    }
+
     pub fn update_layout(&mut self, window_size: Pos) {
         self.window_size = window_size;
 
         //Update commandbuffer
         let command_buffer_viewport_size: Pos = match self.state {
-            EditorState::EditMode => (window_size.x, 0).into(),
-            EditorState::CommandMode => (
+            EditorState::EditMode | EditorState::ConfirmMode => (window_size.x, 0).into(),
+            EditorState::CommandMode
+            | EditorState::SearchMode
+            | EditorState::BufferListMode
+            | EditorState::FinderMode => (
                 window_size.x,
-                (window_size.y.checked_sub(1).unwrap_or_default())
-                    .min(self.command_buffer.content_lines_len()),
+                (window_size.y.saturating_sub(1)).min(self.command_buffer.content_lines_len()),
             )
                 .into(),
-            _ => self.command_buffer.get_viewport_size(),
+            // Room for the prompt
+            EditorState::QuitMode | EditorState::RecoverMode => {
+                (window_size.x, window_size.y.min(1)).into()
+            }
+            EditorState::Init | EditorState::Close => (window_size.x, 0).into(),
         };
         self.command_buffer
             .set_viewport_size(command_buffer_viewport_size);
@@ -101,32 +543,91 @@ impl Editor {
                 0,
                 window_size
                     .y
-                    .checked_sub(1 + command_buffer_viewport_size.y)
-                    .unwrap_or_default(),
+                    .saturating_sub(1 + command_buffer_viewport_size.y),
             )
                 .into(),
         );
 
-        //Update editbuffer
-        self.edit_buffer.set_viewport_size(match self.state {
-            EditorState::EditMode => (
-                window_size.x,
-                window_size.y.checked_sub(1).unwrap_or_default(),
-            )
-                .into(),
-            EditorState::CommandMode => (
-                window_size.x,
-                (window_size.y)
-                    .checked_sub(command_buffer_viewport_size.y + 1)
-                    .unwrap_or_default(),
-            )
-                .into(),
-            _ => self.edit_buffer.get_viewport_size(),
-        });
+        //Update windows
+        self.tab_bar_shown = self.needs_tab_bar();
+        let tab_bar_height = usize::from(self.tab_bar_shown);
+        self.edit_zone_size = Pos::new(
+            window_size.x,
+            window_size
+                .y
+                .saturating_sub(command_buffer_viewport_size.y + 1 + tab_bar_height),
+        );
+        self.borders.clear();
+        self.layout.arrange(
+            Pos::new(0, tab_bar_height),
+            self.edit_zone_size,
+            &mut self.windows,
+            &mut self.borders,
+        );
+        for index in 0..self.windows.len() {
+            let gutter_width = self.needed_gutter_width(index);
+            self.windows[index].gutter_width = gutter_width;
+        }
+        self.place_buffer(self.focused);
 
         self.need_full_clear = true;
     }
 
+    /// The tab bar is only there to go from a buffer to another
+    fn needs_tab_bar(&self) -> bool {
+        self.documents.len() > 1 && self.window_size.y > 1
+    }
+
+    /// Tabs of the open documents, laid out on the width of the terminal
+    fn tab_bar(&self) -> TabBar {
+        let labels = self
+            .document_states()
+            .iter()
+            .map(|(filename, modified)| {
+                format!(
+                    " {}{} ",
+                    filename
+                        .and_then(|f| f.file_name())
+                        .map_or("[No Name]".into(), |f| f.to_string_lossy()),
+                    if *modified { " [+]" } else { "" }
+                )
+            })
+            .collect();
+        TabBar::new(labels, self.current, self.window_size.x)
+    }
+
+    /// Width of the line numbers of a window, along with the space separating
+    /// them from the text
+    fn needed_gutter_width(&self, window: usize) -> usize {
+        let window = &self.windows[window];
+        let width = match self.config.line_numbers {
+            LineNumbers::None => 0,
+            LineNumbers::Absolute | LineNumbers::Relative => {
+                let lines = self.document_buffer(window.document).content_lines_len();
+                lines.to_string().len() + 1
+            }
+        };
+        width.min(window.size.x)
+    }
+
+    /// Lays the buffer of a window out in it, between its line numbers and
+    /// its status line
+    fn place_buffer(&mut self, window: usize) {
+        let window = self.windows[window].clone();
+        // A single window uses the status bar of the editor
+        let status_height = usize::from(self.windows.len() > 1);
+        let (wrap, show_overflow_indicators) =
+            (self.config.wrap, self.config.show_overflow_indicators);
+        let buffer = self.document_buffer_mut(window.document);
+        buffer.set_top_left_corner(window.top_left + (window.gutter_width, 0));
+        buffer.set_show_overflow_indicators(show_overflow_indicators);
+        buffer.set_wrap(wrap);
+        buffer.set_viewport_size(Pos::new(
+            window.size.x - window.gutter_width,
+            window.size.y.saturating_sub(status_height),
+        ));
+    }
+
     pub fn cleanup(stdout: &mut Stdout) -> Result<()> {
         queue!(
             stdout,
@@ -152,36 +653,109 @@ impl Editor {
 
         queue!(
             stdout,
-            style::SetColors(self.config.color_status_bar.into()),
+            SetStyle(self.theme.status_bar),
             terminal::Clear(terminal::ClearType::All),
             cursor::EnableBlinking,
             cursor::MoveTo(0, 0),
-            style::SetColors(self.config.color_edit_zone.into()),
+            SetStyle(self.theme.edit_zone),
         )?;
 
-        self.set_state(EditorState::EditMode);
+        self.set_state(if self.recovery.is_some() {
+            EditorState::RecoverMode
+        } else {
+            EditorState::EditMode
+        });
         Ok(())
     }
 
     fn execute_command(&mut self, command: &EditorCommand) -> Result<()> {
         match command {
-            EditorCommand::SetFilename(filename) => {
-                self.filename = if filename.is_empty() {
-                    None
-                } else {
-                    Some(PathBuf::from(filename))
+            EditorCommand::SetFilename(filename) => self.set_filename(if filename.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(filename))
+            }),
+            EditorCommand::SaveAs(filename) => {
+                let path = PathBuf::from(filename);
+                self.edit_buffer.save_to_file(&path)?;
+                self.set_filename(Some(path));
+            }
+            EditorCommand::Open(path) => self.open_document(path)?,
+            EditorCommand::SwitchBuffer(name) => self.switch_document(self.find_document(name)?),
+            EditorCommand::NextBuffer => {
+                self.switch_document((self.current + 1) % self.documents.len())
+            }
+            EditorCommand::PreviousBuffer => self
+                .switch_document((self.current + self.documents.len() - 1) % self.documents.len()),
+            EditorCommand::CloseBuffer => self.close_document(),
+            EditorCommand::ListBuffers => self.list_buffers(),
+            EditorCommand::FindFile => {
+                self.finder = Some(Finder::new(std::env::current_dir()?));
+                self.command_buffer.empty_content();
+                self.set_state(EditorState::FinderMode);
+            }
+            EditorCommand::Split(direction) => self.split_window(*direction),
+            EditorCommand::CloseWindow => self.close_window()?,
+            EditorCommand::NextWindow => self.cycle_window(true),
+            EditorCommand::PreviousWindow => self.cycle_window(false),
+            EditorCommand::ResizeWindow { direction, delta } => {
+                if !self
+                    .layout
+                    .resize(self.focused, *direction, *delta, self.edit_zone_size)
+                {
+                    return Err(anyhow!("No window to resize against"));
                 }
+                self.update_layout(self.window_size);
             }
-            EditorCommand::SaveAs(filename) => {
-                self.filename = Some(PathBuf::from(filename));
-                self.edit_buffer
-                    .save_to_file(self.filename.clone().unwrap())?;
+            EditorCommand::SelectRegister(register) => {
+                self.register = *register;
+                self.status_message = Some(format!("Register {register}"));
+            }
+            EditorCommand::SetLineEnding(line_ending) => {
+                self.edit_buffer.set_line_ending(*line_ending);
+            }
+            EditorCommand::Substitute {
+                pattern,
+                replacement,
+                scope,
+                confirm,
+            } => {
+                let cursor = self.edit_buffer.get_cursor();
+                let (start, end) = match scope {
+                    SubstituteScope::Line => {
+                        (Pos::new(0, cursor.y), Pos::new(usize::MAX, cursor.y))
+                    }
+                    SubstituteScope::Selection => self
+                        .edit_buffer
+                        .get_selection()
+                        .context("Nothing is selected")?,
+                    SubstituteScope::All => (Pos::default(), Pos::new(usize::MAX, usize::MAX)),
+                };
+                let substitution = Substitution::new(
+                    &Search::from_pattern(pattern, true)?,
+                    replacement,
+                    &self.edit_buffer,
+                    start,
+                    end,
+                );
+                self.edit_buffer.begin_transaction();
+                self.substitution = Some(substitution);
+                if *confirm {
+                    self.set_state(EditorState::ConfirmMode);
+                    self.next_substitution();
+                } else {
+                    self.replace_all_substitutions()?;
+                }
+                self.need_full_clear = true;
             }
         }
         Ok(())
     }
 
     pub fn process_event(&mut self, event: Event) -> Result<()> {
+        if let event::Event::Key(_) = event {
+            self.status_message = None;
+        }
         match event {
             event::Event::Resize(width, height) => {
                 self.update_layout((width, height).into());
@@ -190,94 +764,143 @@ impl Editor {
             event => match self.state {
                 EditorState::EditMode => self.process_event_edit_mode(event),
                 EditorState::CommandMode => self.process_event_command_mode(event),
+                EditorState::SearchMode => self.process_event_search_mode(event),
+                EditorState::ConfirmMode => self.process_event_confirm_mode(event),
+                EditorState::QuitMode => self.process_event_quit_mode(event),
+                EditorState::RecoverMode => self.process_event_recover_mode(event),
+                EditorState::BufferListMode => self.process_event_buffer_list_mode(event),
+                EditorState::FinderMode => self.process_event_finder_mode(event),
                 _ => Ok(()),
             },
         }
     }
 
     fn process_event_command_mode(&mut self, event: Event) -> Result<()> {
+        // Candidates only stay listed until the next key
+        if let Some(command) = self.hidden_command.take() {
+            self.command_buffer = command;
+            self.update_layout(self.window_size);
+        }
         match event {
-            event::Event::Key(key_event) => {
-                if key_event.kind == KeyEventKind::Press {
-                    self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
-                    match self
-                        .config
-                        .command_keybindings
-                        .clone()
-                        .get(&self.last_keypress)
-                    {
-                        Some(action) => match action {
-                            EditorAction::Quit => self.set_state(EditorState::Close),
-                            EditorAction::MoveUp => {
-                                self.command_buffer.move_up();
-                            }
-                            EditorAction::MoveDown => {
-                                self.command_buffer.move_down();
-                            }
-                            EditorAction::MoveRight => {
-                                self.command_buffer.move_right();
-                            }
-                            EditorAction::MoveLeft => {
-                                self.command_buffer.move_left();
-                            }
-                            EditorAction::PageUp => {
-                                self.command_buffer.move_up_n(
-                                    self.command_buffer
-                                        .get_viewport_size()
-                                        .y
-                                        .checked_sub(1)
-                                        .unwrap_or_default(),
-                                );
-                            }
-                            EditorAction::PageDown => {
-                                self.command_buffer.move_down_n(
-                                    self.command_buffer
-                                        .get_viewport_size()
-                                        .y
-                                        .checked_sub(1)
-                                        .unwrap_or_default(),
-                                );
-                            }
-                            EditorAction::MoveLineEnd => {
-                                self.command_buffer.move_line_end();
-                            }
-                            EditorAction::MoveLineStart => {
-                                self.command_buffer.move_start_line();
-                            }
-                            EditorAction::DeleteCharBack => {
-                                self.command_buffer.delete_n_chars_back_from_cursor(1)?;
-                                self.need_full_clear = true;
-                            }
-                            EditorAction::DeleteCharFront => {
-                                self.command_buffer.delete_n_chars_front_from_cursor(1)?;
-                                self.need_full_clear = true;
-                            }
-                            EditorAction::GoIntoEditMode => self.set_state(EditorState::EditMode),
-                            EditorAction::DeleteAll => self.command_buffer.empty_content(),
-                            EditorAction::Command(cmd) => self.execute_command(cmd)?,
-                            EditorAction::GoIntoCommandMode => (),
-                            EditorAction::SaveDocument => (),
-                        },
-                        None => match key_event.code {
-                            event::KeyCode::Enter => {
-                                self.execute_command(&EditorCommand::from_str(
-                                    &self.command_buffer.get_contents(),
-                                )?)?;
-                                self.command_buffer.empty_content();
-                                self.set_state(EditorState::EditMode);
-                            }
-                            event::KeyCode::Char(c) => {
+            event::Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+                match self
+                    .config
+                    .command_keybindings
+                    .clone()
+                    .get(&self.last_keypress)
+                {
+                    Some(action) => match action {
+                        EditorAction::Quit => self.quit(),
+                        EditorAction::ForceQuit => self.set_state(EditorState::Close),
+                        EditorAction::MoveUp => {
+                            self.command_buffer.move_up();
+                        }
+                        EditorAction::MoveDown => {
+                            self.command_buffer.move_down();
+                        }
+                        EditorAction::MoveRight => {
+                            self.command_buffer.move_right();
+                        }
+                        EditorAction::MoveLeft => {
+                            self.command_buffer.move_left();
+                        }
+                        EditorAction::PageUp => {
+                            self.command_buffer.move_up_n(
+                                self.command_buffer.get_viewport_size().y.saturating_sub(1),
+                            );
+                        }
+                        EditorAction::PageDown => {
+                            self.command_buffer.move_down_n(
+                                self.command_buffer.get_viewport_size().y.saturating_sub(1),
+                            );
+                        }
+                        EditorAction::MoveLineEnd => {
+                            self.command_buffer.move_line_end();
+                        }
+                        EditorAction::MoveLineStart => {
+                            self.command_buffer.move_start_line();
+                        }
+                        EditorAction::DeleteCharBack => {
+                            self.command_buffer.delete_n_chars_back_from_cursor(1)?;
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::DeleteCharFront => {
+                            self.command_buffer.delete_n_chars_front_from_cursor(1)?;
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::GoIntoEditMode => self.set_state(EditorState::EditMode),
+                        EditorAction::DeleteAll => self.command_buffer.empty_content(),
+                        EditorAction::Undo => {
+                            self.command_buffer.undo();
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::Redo => {
+                            self.command_buffer.redo();
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::Paste => {
+                            let register = self.take_register();
+                            if let Some(text) = self.clipboard.get(register) {
                                 self.command_buffer
-                                    .add_str_at_cursor(format!("{}", c).as_str())?;
+                                    .add_str_at_cursor(&text.lines().collect::<String>())?;
                             }
-                            keycode => {
-                                self.command_buffer
-                                    .add_str_at_cursor(format!("{}", keycode).as_str())?;
+                        }
+                        EditorAction::Copy | EditorAction::Cut => (),
+                        EditorAction::Complete => self.complete_command()?,
+                        EditorAction::Search
+                        | EditorAction::NextMatch
+                        | EditorAction::PreviousMatch => (),
+                        EditorAction::Command(cmd) => {
+                            if let Err(err) = self.execute_command(cmd) {
+                                self.status_message = Some(format!("Error : {err:#}"));
                             }
-                        },
-                    }
+                        }
+                        EditorAction::GoIntoCommandMode => (),
+                        EditorAction::SaveDocument => (),
+                        EditorAction::SelectUp
+                        | EditorAction::SelectDown
+                        | EditorAction::SelectRight
+                        | EditorAction::SelectLeft
+                        | EditorAction::SelectLineStart
+                        | EditorAction::SelectLineEnd => (),
+                    },
+                    None => match key_event.code {
+                        event::KeyCode::Enter => {
+                            let command = self.command_buffer.get_contents();
+                            self.command_buffer.empty_content();
+                            let close_after_save = self.close_after_save;
+                            self.set_state(EditorState::EditMode);
+                            if let Err(err) =
+                                EditorCommand::from_str(&command).and_then(|command| {
+                                    self.execute_command(&command)?;
+                                    // Only a successful save goes on closing
+                                    if close_after_save
+                                        && matches!(command, EditorCommand::SaveAs(_))
+                                    {
+                                        self.continue_closing();
+                                    }
+                                    Ok(())
+                                })
+                            {
+                                self.status_message = Some(format!("Error : {err:#}"));
+                            }
+                        }
+                        event::KeyCode::Char(c) => {
+                            self.command_buffer
+                                .add_str_at_cursor(format!("{}", c).as_str())?;
+                        }
+                        keycode => {
+                            self.command_buffer
+                                .add_str_at_cursor(format!("{}", keycode).as_str())?;
+                        }
+                    },
                 }
             }
+            event::Event::Paste(text) => {
+                self.command_buffer
+                    .add_str_at_cursor(&text.lines().collect::<String>())?;
+            }
             // event::Event::Mouse(mouse_event) => match mouse_event.kind {
             //     event::MouseEventKind::ScrollDown => {
             //         self.command_buffer.move_down();
@@ -305,112 +928,731 @@ impl Editor {
         Ok(())
     }
 
-    fn process_event_edit_mode(&mut self, event: Event) -> Result<()> {
+    /// Completes the command name or path before the cursor in the command
+    /// zone, listing the candidates below the command if there are several
+    fn complete_command(&mut self) -> Result<()> {
+        let cursor = self.command_buffer.get_cursor();
+        let command = self.command_buffer.get_contents();
+        let before_cursor: String = command.graphemes(true).take(cursor.x).collect();
+        let completion = completion::complete(
+            &before_cursor,
+            EditorCommand::NAMES,
+            EditorCommand::PATH_NAMES,
+        );
+        if !completion.insert.is_empty() {
+            self.command_buffer.add_str_at_cursor(&completion.insert)?;
+        }
+        if !completion.candidates.is_empty() {
+            let cursor = self.command_buffer.get_cursor();
+            let listed = Buffer::load_from_str(&format!(
+                "{}\n{}",
+                self.command_buffer
+                    .get_contents()
+                    .trim_end_matches(['\r', '\n']),
+                completion::columns(&completion.candidates, self.window_size.x)
+            ));
+            self.hidden_command = Some(std::mem::replace(&mut self.command_buffer, listed));
+            self.update_layout(self.window_size);
+            self.command_buffer.move_cursor(cursor);
+        }
+        Ok(())
+    }
+
+    fn process_event_finder_mode(&mut self, event: Event) -> Result<()> {
         match event {
-            event::Event::Key(key_event) => {
-                if key_event.kind == KeyEventKind::Press {
-                    self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
-                    match self
-                        .config
-                        .edit_keybindings
-                        .clone()
-                        .get(&self.last_keypress)
-                    {
-                        Some(action) => match action {
-                            EditorAction::Quit => self.set_state(EditorState::Close),
-                            EditorAction::MoveUp => {
-                                self.edit_buffer.move_up();
-                            }
-                            EditorAction::MoveDown => {
-                                self.edit_buffer.move_down();
-                            }
-                            EditorAction::MoveRight => {
-                                self.edit_buffer.move_right();
-                            }
-                            EditorAction::MoveLeft => {
-                                self.edit_buffer.move_left();
-                            }
-                            EditorAction::PageUp => {
-                                self.edit_buffer.move_up_n(
-                                    self.edit_buffer
-                                        .get_viewport_size()
-                                        .y
-                                        .checked_sub(1)
-                                        .unwrap_or_default(),
-                                );
-                            }
-                            EditorAction::PageDown => {
-                                self.edit_buffer.move_down_n(
-                                    self.edit_buffer
-                                        .get_viewport_size()
-                                        .y
-                                        .checked_sub(1)
-                                        .unwrap_or_default(),
-                                );
-                            }
-                            EditorAction::MoveLineEnd => {
-                                self.edit_buffer.move_line_end();
+            event::Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+                let page = self.edit_zone_size.y.saturating_sub(2) as isize;
+                match self
+                    .config
+                    .command_keybindings
+                    .clone()
+                    .get(&self.last_keypress)
+                {
+                    Some(action) => match action {
+                        EditorAction::Quit => self.quit(),
+                        EditorAction::ForceQuit => self.set_state(EditorState::Close),
+                        EditorAction::GoIntoEditMode => {
+                            self.finder = None;
+                            self.command_buffer.empty_content();
+                            self.set_state(EditorState::EditMode);
+                        }
+                        EditorAction::MoveUp => self.move_finder_selection(-1),
+                        EditorAction::MoveDown => self.move_finder_selection(1),
+                        EditorAction::PageUp => self.move_finder_selection(-page),
+                        EditorAction::PageDown => self.move_finder_selection(page),
+                        EditorAction::MoveRight => {
+                            self.command_buffer.move_right();
+                        }
+                        EditorAction::MoveLeft => {
+                            self.command_buffer.move_left();
+                        }
+                        EditorAction::MoveLineEnd => {
+                            self.command_buffer.move_line_end();
+                        }
+                        EditorAction::MoveLineStart => {
+                            self.command_buffer.move_start_line();
+                        }
+                        EditorAction::DeleteCharBack => {
+                            self.command_buffer.delete_n_chars_back_from_cursor(1)?;
+                            self.update_finder();
+                        }
+                        EditorAction::DeleteCharFront => {
+                            self.command_buffer.delete_n_chars_front_from_cursor(1)?;
+                            self.update_finder();
+                        }
+                        EditorAction::DeleteAll => {
+                            self.command_buffer.empty_content();
+                            self.update_finder();
+                        }
+                        _ => (),
+                    },
+                    None => match key_event.code {
+                        event::KeyCode::Enter => {
+                            let path = self.finder.take().and_then(|f| f.selected_path());
+                            self.command_buffer.empty_content();
+                            self.set_state(EditorState::EditMode);
+                            if let Some(path) = path {
+                                if let Err(err) = self.open_document(&path.to_string_lossy()) {
+                                    self.status_message = Some(format!("Error : {err:#}"));
+                                }
                             }
-                            EditorAction::MoveLineStart => {
-                                self.edit_buffer.move_start_line();
+                        }
+                        event::KeyCode::Char(c) => {
+                            self.command_buffer
+                                .add_str_at_cursor(format!("{}", c).as_str())?;
+                            self.update_finder();
+                        }
+                        _ => (),
+                    },
+                }
+            }
+            event::Event::Paste(text) => {
+                self.command_buffer
+                    .add_str_at_cursor(&text.lines().collect::<String>())?;
+                self.update_finder();
+            }
+            _ => (),
+        };
+
+        Ok(())
+    }
+
+    /// Matches the files against the query being typed
+    fn update_finder(&mut self) {
+        if let Some(finder) = self.finder.as_mut() {
+            finder.set_query(&self.command_buffer.line(0).unwrap_or_default());
+        }
+    }
+
+    fn move_finder_selection(&mut self, offset: isize) {
+        if let Some(finder) = self.finder.as_mut() {
+            finder.move_selection(offset);
+        }
+    }
+
+    fn process_event_search_mode(&mut self, event: Event) -> Result<()> {
+        match event {
+            event::Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+                match self
+                    .config
+                    .command_keybindings
+                    .clone()
+                    .get(&self.last_keypress)
+                {
+                    Some(action) => match action {
+                        EditorAction::Quit => self.quit(),
+                        EditorAction::ForceQuit => self.set_state(EditorState::Close),
+                        EditorAction::GoIntoEditMode => {
+                            self.edit_buffer.move_cursor(self.search_origin);
+                            self.search_query.clear();
+                            self.command_buffer.empty_content();
+                            self.set_state(EditorState::EditMode);
+                        }
+                        EditorAction::NextMatch => self.jump_to_match(true)?,
+                        EditorAction::PreviousMatch => self.jump_to_match(false)?,
+                        EditorAction::MoveRight => {
+                            self.command_buffer.move_right();
+                        }
+                        EditorAction::MoveLeft => {
+                            self.command_buffer.move_left();
+                        }
+                        EditorAction::MoveLineEnd => {
+                            self.command_buffer.move_line_end();
+                        }
+                        EditorAction::MoveLineStart => {
+                            self.command_buffer.move_start_line();
+                        }
+                        EditorAction::DeleteCharBack => {
+                            self.command_buffer.delete_n_chars_back_from_cursor(1)?;
+                            self.update_search()?;
+                        }
+                        EditorAction::DeleteCharFront => {
+                            self.command_buffer.delete_n_chars_front_from_cursor(1)?;
+                            self.update_search()?;
+                        }
+                        EditorAction::DeleteAll => {
+                            self.command_buffer.empty_content();
+                            self.update_search()?;
+                        }
+                        _ => (),
+                    },
+                    None => match key_event.code {
+                        event::KeyCode::Enter => {
+                            self.command_buffer.empty_content();
+                            self.set_state(EditorState::EditMode);
+                        }
+                        event::KeyCode::Char(c) => {
+                            self.command_buffer
+                                .add_str_at_cursor(format!("{}", c).as_str())?;
+                            self.update_search()?;
+                        }
+                        _ => (),
+                    },
+                }
+            }
+            event::Event::Paste(text) => {
+                self.command_buffer
+                    .add_str_at_cursor(&text.lines().collect::<String>())?;
+                self.update_search()?;
+            }
+            _ => (),
+        };
+
+        Ok(())
+    }
+
+    /// Moves the cursor to the first match of the query being typed, starting
+    /// from where the search began
+    fn update_search(&mut self) -> Result<()> {
+        self.search_query = self.command_buffer.line(0).unwrap_or_default().to_string();
+        self.edit_buffer.clear_selection();
+        if self.search_query.is_empty() {
+            self.edit_buffer.move_cursor(self.search_origin);
+            return Ok(());
+        }
+        let search = Search::new(&self.search_query, self.config.search_case_sensitive)?;
+        match search.next(
+            &self.edit_buffer,
+            self.search_origin,
+            self.config.search_wrap_around,
+        ) {
+            Some((start, _)) => self.edit_buffer.move_cursor(start),
+            None => self.edit_buffer.move_cursor(self.search_origin),
+        }
+        Ok(())
+    }
+
+    /// Moves the cursor to the next or previous match of the last query
+    fn jump_to_match(&mut self, forward: bool) -> Result<()> {
+        if self.search_query.is_empty() {
+            return Ok(());
+        }
+        let search = Search::new(&self.search_query, self.config.search_case_sensitive)?;
+        let cursor = self.edit_buffer.get_cursor();
+        let found = if forward {
+            search.next(
+                &self.edit_buffer,
+                cursor + (1, 0),
+                self.config.search_wrap_around,
+            )
+        } else {
+            search.previous(&self.edit_buffer, cursor, self.config.search_wrap_around)
+        };
+        if let Some((start, _)) = found {
+            self.edit_buffer.clear_selection();
+            self.edit_buffer.move_cursor(start);
+            self.search_origin = start;
+        }
+        Ok(())
+    }
+
+    fn process_event_confirm_mode(&mut self, event: Event) -> Result<()> {
+        if let event::Event::Key(key_event) = event {
+            if key_event.kind != KeyEventKind::Press {
+                return Ok(());
+            }
+            self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+            let Some(substitution) = self.substitution.as_mut() else {
+                self.set_state(EditorState::EditMode);
+                return Ok(());
+            };
+            match key_event.code {
+                event::KeyCode::Char('y') | event::KeyCode::Enter => {
+                    substitution.replace_current(&mut self.edit_buffer)?;
+                    self.next_substitution();
+                }
+                event::KeyCode::Char('n') => {
+                    substitution.skip_current();
+                    self.next_substitution();
+                }
+                event::KeyCode::Char('a') => self.replace_all_substitutions()?,
+                event::KeyCode::Char('q') | event::KeyCode::Esc => self.finish_substitution(),
+                _ => self.next_substitution(),
+            }
+            self.need_full_clear = true;
+        }
+        Ok(())
+    }
+
+    /// Closes the editor, unless a document has unsaved changes, in which
+    /// case it is shown and the user is asked whether to save them first
+    fn quit(&mut self) {
+        self.closing = Closing::Editor;
+        if !self.edit_buffer.is_modified() {
+            if let Some(index) = self
+                .documents
+                .iter()
+                .position(|document| document.buffer.is_modified())
+            {
+                self.switch_document(index);
+            }
+        }
+        if self.edit_buffer.is_modified() {
+            self.set_state(EditorState::QuitMode);
+        } else {
+            self.set_state(EditorState::Close);
+        }
+    }
+
+    /// Closes the document whose unsaved changes were just saved or
+    /// discarded, then the editor if that is what was being closed
+    fn continue_closing(&mut self) {
+        self.remove_document();
+        match self.closing {
+            Closing::Editor => self.quit(),
+            Closing::Document => self.set_state(EditorState::EditMode),
+        }
+    }
+
+    /// Question asked in the command zone, if any
+    fn prompt(&self) -> Option<&'static str> {
+        match self.state {
+            EditorState::QuitMode => Some(QUIT_PROMPT),
+            EditorState::RecoverMode => Some(RECOVER_PROMPT),
+            _ => None,
+        }
+    }
+
+    /// Start of the command zone line prompts are printed on
+    fn prompt_pos(&self) -> Pos {
+        Pos::new(0, self.window_size.y.saturating_sub(2))
+    }
+
+    fn process_event_quit_mode(&mut self, event: Event) -> Result<()> {
+        if let event::Event::Key(key_event) = event {
+            if key_event.kind != KeyEventKind::Press {
+                return Ok(());
+            }
+            self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+            match key_event.code {
+                event::KeyCode::Char('s') | event::KeyCode::Char('y') => {
+                    match self.filename.clone() {
+                        Some(path) => match self.edit_buffer.save_to_file(path) {
+                            Ok(()) => self.continue_closing(),
+                            Err(err) => {
+                                self.status_message = Some(format!("Error : {err:#}"));
+                                self.set_state(EditorState::EditMode);
                             }
-                            EditorAction::SaveDocument => {
-                                if let Some(path) = self.filename.clone() {
-                                    self.edit_buffer.save_to_file(path)?;
-                                } else {
-                                    self.command_buffer = Buffer::load_from_str("save_as ");
-                                    self.set_state(EditorState::CommandMode);
-                                    self.command_buffer.move_line_end();
+                        },
+                        None => {
+                            self.close_after_save = true;
+                            self.command_buffer = Buffer::load_from_str("save_as ");
+                            self.set_state(EditorState::CommandMode);
+                            self.command_buffer.move_line_end();
+                        }
+                    }
+                }
+                event::KeyCode::Char('d') | event::KeyCode::Char('n') => self.continue_closing(),
+                event::KeyCode::Char('c') | event::KeyCode::Esc => {
+                    self.set_state(EditorState::EditMode)
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn process_event_recover_mode(&mut self, event: Event) -> Result<()> {
+        let event::Event::Key(key_event) = event else {
+            return Ok(());
+        };
+        if key_event.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+        let Some(recovery) = self.recovery.clone() else {
+            self.set_state(EditorState::EditMode);
+            return Ok(());
+        };
+        match key_event.code {
+            event::KeyCode::Char('r') => {
+                self.show_document();
+                self.edit_buffer.replace_contents(&recovery.contents)?;
+                self.recovery = None;
+                self.set_state(EditorState::EditMode);
+            }
+            event::KeyCode::Char('v') => {
+                match self.hidden_document.take() {
+                    Some(_) => self.show_document(),
+                    None => {
+                        let diff = Buffer::load_from_str(&recovery.diff(&self.edit_buffer));
+                        self.hidden_document = Some(std::mem::replace(&mut self.edit_buffer, diff));
+                    }
+                }
+                self.update_layout(self.window_size);
+            }
+            event::KeyCode::Char('d') => {
+                self.show_document();
+                self.recovery = None;
+                self.set_state(EditorState::EditMode);
+                if let Err(err) = recovery.discard() {
+                    self.status_message = Some(format!("Error : {err:#}"));
+                }
+            }
+            // Leaves the swap file for later, closing the document only if
+            // others are open
+            event::KeyCode::Char('q') | event::KeyCode::Esc => {
+                self.show_document();
+                self.recovery = None;
+                if self.documents.len() == 1 {
+                    self.set_state(EditorState::Close);
+                } else {
+                    self.remove_document();
+                    self.set_state(EditorState::EditMode);
+                }
+            }
+            _ => match self.config.edit_keybindings.get(&self.last_keypress) {
+                Some(EditorAction::MoveUp) => self.edit_buffer.move_up(),
+                Some(EditorAction::MoveDown) => self.edit_buffer.move_down(),
+                Some(EditorAction::PageUp) => self
+                    .edit_buffer
+                    .move_up_n(self.edit_buffer.get_viewport_size().y.saturating_sub(1)),
+                Some(EditorAction::PageDown) => self
+                    .edit_buffer
+                    .move_down_n(self.edit_buffer.get_viewport_size().y.saturating_sub(1)),
+                _ => (),
+            },
+        }
+        Ok(())
+    }
+
+    fn process_event_buffer_list_mode(&mut self, event: Event) -> Result<()> {
+        let event::Event::Key(key_event) = event else {
+            return Ok(());
+        };
+        if key_event.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+        match self.config.command_keybindings.get(&self.last_keypress) {
+            Some(EditorAction::Quit) => self.quit(),
+            Some(EditorAction::ForceQuit) => self.set_state(EditorState::Close),
+            Some(EditorAction::GoIntoEditMode) => self.set_state(EditorState::EditMode),
+            Some(EditorAction::MoveUp) => self.command_buffer.move_up(),
+            Some(EditorAction::MoveDown) => self.command_buffer.move_down(),
+            Some(EditorAction::PageUp) => self
+                .command_buffer
+                .move_up_n(self.command_buffer.get_viewport_size().y.saturating_sub(1)),
+            Some(EditorAction::PageDown) => self
+                .command_buffer
+                .move_down_n(self.command_buffer.get_viewport_size().y.saturating_sub(1)),
+            _ => {
+                if key_event.code == event::KeyCode::Enter {
+                    let index = self.command_buffer.get_cursor().y;
+                    self.command_buffer.empty_content();
+                    self.set_state(EditorState::EditMode);
+                    self.switch_document(index);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the document back in the edit zone if the diff of the swap file
+    /// was shown instead
+    fn show_document(&mut self) {
+        if let Some(document) = self.hidden_document.take() {
+            self.edit_buffer = document;
+            if let Some(highlighter) = &mut self.highlighter {
+                highlighter.invalidate_from(0);
+            }
+        }
+    }
+
+    /// Moves to the next match of the pending substitution and asks for
+    /// confirmation, or ends it if there are no more matches
+    fn next_substitution(&mut self) {
+        let Some(substitution) = self.substitution.as_mut() else {
+            return;
+        };
+        let found = match substitution.current() {
+            Some(current) => Some(current),
+            None => substitution.find_next(&self.edit_buffer),
+        };
+        match found {
+            Some((start, _)) => {
+                self.edit_buffer.move_cursor(start);
+                self.status_message = Some("Replace ? (y)es, (n)o, (a)ll, (q)uit".to_string());
+            }
+            None => self.finish_substitution(),
+        }
+    }
+
+    /// Replaces every remaining match of the pending substitution
+    fn replace_all_substitutions(&mut self) -> Result<()> {
+        if let Some(substitution) = self.substitution.as_mut() {
+            while substitution.current().is_some()
+                || substitution.find_next(&self.edit_buffer).is_some()
+            {
+                substitution.replace_current(&mut self.edit_buffer)?;
+            }
+        }
+        self.finish_substitution();
+        Ok(())
+    }
+
+    /// Closes the undo step of the pending substitution and reports how many
+    /// matches were replaced
+    fn finish_substitution(&mut self) {
+        if let Some(substitution) = self.substitution.take() {
+            self.edit_buffer.end_transaction();
+            self.status_message = Some(format!("{} replacement(s)", substitution.count()));
+        }
+        if self.state == EditorState::ConfirmMode {
+            self.set_state(EditorState::EditMode);
+        }
+    }
+
+    fn process_event_edit_mode(&mut self, event: Event) -> Result<()> {
+        match event {
+            event::Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.last_keypress = format!("{}{}", key_event.modifiers, key_event.code);
+                match self
+                    .config
+                    .edit_keybindings
+                    .clone()
+                    .get(&self.last_keypress)
+                {
+                    Some(action) => match action {
+                        EditorAction::Quit => self.quit(),
+                        EditorAction::ForceQuit => self.set_state(EditorState::Close),
+                        EditorAction::MoveUp => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_up();
+                        }
+                        EditorAction::MoveDown => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_down();
+                        }
+                        EditorAction::MoveRight => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_right();
+                        }
+                        EditorAction::MoveLeft => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_left();
+                        }
+                        EditorAction::PageUp => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_up_n(
+                                self.edit_buffer.get_viewport_size().y.saturating_sub(1),
+                            );
+                        }
+                        EditorAction::PageDown => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_down_n(
+                                self.edit_buffer.get_viewport_size().y.saturating_sub(1),
+                            );
+                        }
+                        EditorAction::MoveLineEnd => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_line_end();
+                        }
+                        EditorAction::MoveLineStart => {
+                            self.edit_buffer.clear_selection();
+                            self.edit_buffer.move_start_line();
+                        }
+                        EditorAction::SelectUp => {
+                            self.edit_buffer.start_selection();
+                            self.edit_buffer.move_up();
+                        }
+                        EditorAction::SelectDown => {
+                            self.edit_buffer.start_selection();
+                            self.edit_buffer.move_down();
+                        }
+                        EditorAction::SelectRight => {
+                            self.edit_buffer.start_selection();
+                            self.edit_buffer.move_right();
+                        }
+                        EditorAction::SelectLeft => {
+                            self.edit_buffer.start_selection();
+                            self.edit_buffer.move_left();
+                        }
+                        EditorAction::SelectLineEnd => {
+                            self.edit_buffer.start_selection();
+                            self.edit_buffer.move_line_end();
+                        }
+                        EditorAction::SelectLineStart => {
+                            self.edit_buffer.start_selection();
+                            self.edit_buffer.move_start_line();
+                        }
+                        EditorAction::SaveDocument => {
+                            if let Some(path) = self.filename.clone() {
+                                if let Err(err) = self.edit_buffer.save_to_file(path) {
+                                    self.status_message = Some(format!("Error : {err:#}"));
                                 }
+                            } else {
+                                self.command_buffer = Buffer::load_from_str("save_as ");
+                                self.set_state(EditorState::CommandMode);
+                                self.command_buffer.move_line_end();
                             }
-                            EditorAction::DeleteCharBack => {
+                        }
+                        EditorAction::DeleteCharBack => {
+                            if !self.edit_buffer.delete_selection() {
                                 self.edit_buffer.delete_n_chars_back_from_cursor(1)?;
-                                self.need_full_clear = true;
                             }
-                            EditorAction::DeleteCharFront => {
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::DeleteCharFront => {
+                            if !self.edit_buffer.delete_selection() {
                                 self.edit_buffer.delete_n_chars_front_from_cursor(1)?;
-                                self.need_full_clear = true;
                             }
-                            EditorAction::GoIntoCommandMode => {
-                                self.set_state(EditorState::CommandMode)
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::GoIntoCommandMode => self.set_state(EditorState::CommandMode),
+                        EditorAction::Search => {
+                            self.search_origin = self.edit_buffer.get_cursor();
+                            self.search_query.clear();
+                            self.command_buffer.empty_content();
+                            self.set_state(EditorState::SearchMode);
+                        }
+                        EditorAction::NextMatch => self.jump_to_match(true)?,
+                        EditorAction::PreviousMatch => self.jump_to_match(false)?,
+                        EditorAction::DeleteAll => self.edit_buffer.empty_content(),
+                        EditorAction::Undo => {
+                            self.edit_buffer.undo();
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::Redo => {
+                            self.edit_buffer.redo();
+                            self.need_full_clear = true;
+                        }
+                        EditorAction::Copy => {
+                            let register = self.take_register();
+                            if let Some(text) = self.edit_buffer.get_selected_text() {
+                                if let Err(err) = self.clipboard.set(
+                                    register,
+                                    text,
+                                    &self.config.clipboard_bridge,
+                                ) {
+                                    self.status_message = Some(format!("Error : {err:#}"));
+                                }
                             }
-                            EditorAction::DeleteAll => self.edit_buffer.empty_content(),
-                            EditorAction::Command(cmd) => self.execute_command(cmd)?,
-                            EditorAction::GoIntoEditMode => (),
-                        },
-                        None => match key_event.code {
-                            event::KeyCode::Enter => {
-                                self.edit_buffer.add_line_at_cursor()?;
+                        }
+                        EditorAction::Cut => {
+                            let register = self.take_register();
+                            if let Some(text) = self.edit_buffer.get_selected_text() {
+                                if let Err(err) = self.clipboard.set(
+                                    register,
+                                    text,
+                                    &self.config.clipboard_bridge,
+                                ) {
+                                    self.status_message = Some(format!("Error : {err:#}"));
+                                }
+                                self.edit_buffer.delete_selection();
+                                self.need_full_clear = true;
                             }
-                            event::KeyCode::Char(c) => {
-                                self.edit_buffer
-                                    .add_str_at_cursor(format!("{}", c).as_str())?;
+                        }
+                        EditorAction::Paste => {
+                            let register = self.take_register();
+                            if let Some(text) = self.clipboard.get(register) {
+                                self.edit_buffer.paste_str(text)?;
+                                self.need_full_clear = true;
                             }
-                            keycode => {
-                                self.edit_buffer
-                                    .add_str_at_cursor(format!("{}", keycode).as_str())?;
+                        }
+                        EditorAction::Command(cmd) => {
+                            if let Err(err) = self.execute_command(cmd) {
+                                self.status_message = Some(format!("Error : {err:#}"));
                             }
-                        },
+                        }
+                        EditorAction::GoIntoEditMode | EditorAction::Complete => (),
+                    },
+                    None => match key_event.code {
+                        event::KeyCode::Enter => {
+                            self.edit_buffer.replace_selection("\n")?;
+                            self.need_full_clear = true;
+                        }
+                        event::KeyCode::Char(c) => {
+                            self.edit_buffer
+                                .replace_selection(format!("{}", c).as_str())?;
+                        }
+                        keycode => {
+                            self.edit_buffer
+                                .replace_selection(format!("{}", keycode).as_str())?;
+                        }
+                    },
+                }
+            }
+            // Clicking a tab switches to its buffer, scrolling over the tab
+            // bar goes through the buffers
+            event::Event::Mouse(mouse_event) if self.tab_bar_shown && mouse_event.row == 0 => {
+                let len = self.documents.len();
+                match mouse_event.kind {
+                    event::MouseEventKind::Down(event::MouseButton::Left) => {
+                        let tab_bar = self.tab_bar();
+                        let x = mouse_event.column as usize;
+                        if tab_bar.hidden_left && x == 0 {
+                            self.switch_document((self.current + len - 1) % len);
+                        } else if tab_bar.hidden_right && x + 1 == self.window_size.x {
+                            self.switch_document((self.current + 1) % len);
+                        } else if let Some(index) = tab_bar.document_at(x) {
+                            self.switch_document(index);
+                        }
+                    }
+                    event::MouseEventKind::ScrollDown => {
+                        self.switch_document((self.current + 1) % len)
+                    }
+                    event::MouseEventKind::ScrollUp => {
+                        self.switch_document((self.current + len - 1) % len)
                     }
+                    _ => (),
                 }
             }
             event::Event::Mouse(mouse_event) => match mouse_event.kind {
+                // Scrolling moves the cursor, which mustn't select what it
+                // goes over
                 event::MouseEventKind::ScrollDown => {
+                    self.edit_buffer.clear_selection();
                     self.edit_buffer.move_down();
                 }
                 event::MouseEventKind::ScrollUp => {
+                    self.edit_buffer.clear_selection();
                     self.edit_buffer.move_up();
                 }
-                event::MouseEventKind::Down(button) => match button {
-                    event::MouseButton::Left => {
-                        self.edit_buffer.move_cursor_relative(
-                            (mouse_event.column as usize, mouse_event.row as usize).into(),
-                        );
+                event::MouseEventKind::Down(event::MouseButton::Left) => {
+                    let pos = (mouse_event.column, mouse_event.row).into();
+                    if let Some(index) = self.windows.iter().position(|w| w.contains(pos)) {
+                        self.focus_window(index);
                     }
-                    _ => (),
-                },
+                    self.edit_buffer.clear_selection();
+                    self.edit_buffer
+                        .move_cursor_to_viewport_pos((mouse_event.column, mouse_event.row).into());
+                }
+                event::MouseEventKind::Drag(event::MouseButton::Left) => {
+                    // From where the button was pressed, the cursor is still
+                    // there on the first drag
+                    self.edit_buffer.start_selection();
+                    self.edit_buffer
+                        .move_cursor_to_viewport_pos((mouse_event.column, mouse_event.row).into());
+                }
                 _ => (),
             },
+            event::Event::Paste(text) => {
+                self.edit_buffer.paste_str(&text)?;
+                self.need_full_clear = true;
+            }
             _ => (),
         };
 
@@ -422,36 +1664,103 @@ impl Editor {
             self.init(stdout)?;
         }
 
+        // The gutters grow and shrink with the number of lines, and the tab
+        // bar comes and goes with the second buffer
+        if self.needs_tab_bar() != self.tab_bar_shown
+            || (0..self.windows.len())
+                .any(|index| self.needed_gutter_width(index) != self.windows[index].gutter_width)
+        {
+            self.update_layout(self.window_size);
+        }
+
         if self.need_full_clear {
             queue!(
                 stdout,
-                style::SetColors(self.config.color_status_bar.into()),
+                SetStyle(self.theme.status_bar),
                 terminal::Clear(terminal::ClearType::All)
             )?;
         }
 
-        queue!(stdout, style::SetColors(self.config.color_edit_zone.into()))?;
+        if self.tab_bar_shown {
+            self.display_tab_bar(stdout)?;
+        }
+        for index in 0..self.windows.len() {
+            self.display_window(stdout, index)?;
+        }
+        if self.state == EditorState::FinderMode {
+            self.display_finder(stdout)?;
+        }
+        queue!(stdout, SetStyle(self.theme.inactive_status_bar))?;
+        for (top, height) in self.borders.iter() {
+            for y in 0..*height {
+                queue!(
+                    stdout,
+                    cursor::MoveTo::from(*top + (0, y)),
+                    style::Print('│')
+                )?;
+            }
+        }
+
+        if self.state == EditorState::SearchMode && !self.search_query.is_empty() {
+            let search = Search::new(&self.search_query, self.config.search_case_sensitive)?;
+            queue!(stdout, SetStyle(self.theme.search_match))?;
+            for (start, end) in
+                search.matches_in_lines(&self.edit_buffer, self.edit_buffer.get_visible_lines())
+            {
+                for (pos, text) in self.edit_buffer.get_viewport_ranges(start, end).iter() {
+                    queue!(stdout, cursor::MoveTo::from(*pos), style::Print(text))?;
+                }
+            }
+        }
+
+        if let Some((start, end)) = self
+            .substitution
+            .as_ref()
+            .and_then(|substitution| substitution.current())
+        {
+            queue!(stdout, SetStyle(self.theme.search_match))?;
+            for (pos, text) in self.edit_buffer.get_viewport_ranges(start, end).iter() {
+                queue!(stdout, cursor::MoveTo::from(*pos), style::Print(text))?;
+            }
+        }
+
+        if let Some((start, end)) = self.edit_buffer.get_selection() {
+            queue!(stdout, SetStyle(self.theme.selection))?;
+            for (pos, text) in self.edit_buffer.get_viewport_ranges(start, end).iter() {
+                queue!(stdout, cursor::MoveTo::from(*pos), style::Print(text))?;
+            }
+        }
+
+        queue!(stdout, SetStyle(self.theme.command_zone),)?;
 
-        for (pos, line) in self.edit_buffer.get_viewport().iter() {
+        for (pos, line) in self.command_buffer.get_viewport().iter() {
             queue!(
                 stdout,
                 cursor::MoveTo::from(*pos),
                 terminal::Clear(terminal::ClearType::CurrentLine),
-                style::Print(format!("{line}"))
+                style::Print(line)
             )?;
         }
 
-        queue!(
-            stdout,
-            style::SetColors(self.config.color_command_zone.into()),
-        )?;
+        if self.state == EditorState::BufferListMode {
+            let y = self.command_buffer.get_cursor().y;
+            let line_len = self.command_buffer.line_len(y);
+            queue!(stdout, SetStyle(self.theme.selection))?;
+            for (pos, text) in self
+                .command_buffer
+                .get_viewport_ranges(Pos::new(0, y), Pos::new(line_len, y))
+                .iter()
+            {
+                queue!(stdout, cursor::MoveTo::from(*pos), style::Print(text))?;
+            }
+        }
 
-        for (pos, line) in self.command_buffer.get_viewport().iter() {
+        if let Some(prompt) = self.prompt() {
             queue!(
                 stdout,
-                cursor::MoveTo::from(*pos),
+                cursor::MoveTo::from(self.prompt_pos()),
                 terminal::Clear(terminal::ClearType::CurrentLine),
-                style::Print(format!("{line}"))
+                style::Print(prompt)
             )?;
         }
 
@@ -459,31 +1768,394 @@ impl Editor {
         queue!(
             stdout,
             cursor::MoveTo(0, self.window_size.y as u16 - 1),
-            style::SetColors(self.config.color_status_bar.into()),
+            SetStyle(self.theme.status_bar),
             terminal::Clear(terminal::ClearType::CurrentLine),
-            style::Print(format!(
-                "Filename : {:?}, Cursor : {}, Last Key Press : ({})",
-                self.filename,
-                self.edit_buffer.get_cursor(),
-                self.last_keypress,
-            )),
         )?;
+        match &self.status_message {
+            Some(message) => queue!(stdout, style::Print(message))?,
+            // Split windows have a status line each
+            None if self.windows.len() > 1 => (),
+            None => queue!(
+                stdout,
+                style::Print(self.config.status_bar.render(self.window_size.x, |name| {
+                    self.status_value(self.focused, name)
+                }))
+            )?,
+        }
 
         let terminal_cursor_pos = match self.state {
-            EditorState::EditMode => self.edit_buffer.get_viewport_pos(),
-            EditorState::CommandMode => self.command_buffer.get_viewport_pos(),
+            EditorState::EditMode | EditorState::ConfirmMode => self.edit_buffer.get_viewport_pos(),
+            EditorState::CommandMode
+            | EditorState::SearchMode
+            | EditorState::BufferListMode
+            | EditorState::FinderMode => self.command_buffer.get_viewport_pos(),
+            EditorState::QuitMode | EditorState::RecoverMode => {
+                self.prompt_pos() + (self.prompt().unwrap_or_default().width(), 0)
+            }
             _ => (0usize, 0).into(),
         };
         queue!(stdout, cursor::MoveTo::from(terminal_cursor_pos))?;
+        self.clipboard.flush(stdout)?;
 
         stdout.flush()?;
         Ok(())
     }
+
+    /// Prints the files matching the query over the edit zone, with the
+    /// start of the selected one next to them when there is room for it
+    fn display_finder(&mut self, stdout: &mut Stdout) -> Result<()> {
+        let Some(finder) = self.finder.as_mut() else {
+            return Ok(());
+        };
+        finder.poll();
+        finder.load_preview();
+        let finder = &*finder;
+        let top_left = Pos::new(0, usize::from(self.tab_bar_shown));
+        let size = self.edit_zone_size;
+        let list_width = if size.x >= FINDER_PREVIEW_MIN_WIDTH {
+            size.x / 2
+        } else {
+            size.x
+        };
+
+        let header = format!(
+            " {}/{} files{}",
+            finder.match_count(),
+            finder.file_count(),
+            if finder.is_indexing() {
+                ", indexing…"
+            } else {
+                ""
+            }
+        );
+        queue!(
+            stdout,
+            SetStyle(self.theme.inactive_status_bar),
+            cursor::MoveTo::from(top_left),
+            style::Print(format!("{:list_width$}", truncate_end(&header, list_width)))
+        )?;
+
+        let rows = size.y.saturating_sub(1);
+        let first = (finder.selected() + 1).saturating_sub(rows);
+        let mut matches = finder.matches().skip(first);
+        for row in 0..rows {
+            let pos = top_left + (0, row + 1);
+            let Some((path, finder_match)) = matches.next() else {
+                queue!(
+                    stdout,
+                    SetStyle(self.theme.edit_zone),
+                    cursor::MoveTo::from(pos),
+                    style::Print(" ".repeat(list_width))
+                )?;
+                continue;
+            };
+            let style = if first + row == finder.selected() {
+                self.theme.selection
+            } else {
+                self.theme.edit_zone
+            };
+            let text = truncate_end(&format!(" {path}"), list_width);
+            queue!(
+                stdout,
+                SetStyle(style),
+                cursor::MoveTo::from(pos),
+                style::Print(format!("{text:list_width$}"))
+            )?;
+            // The matched characters, after the leading space
+            queue!(stdout, SetStyle(self.theme.search_match))?;
+            let chars: Vec<char> = text.chars().collect();
+            // Without the ellipsis ending a truncated path, if there is room
+            // for anything
+            let shown = if text.width() < path.width() + 1 {
+                chars.len().saturating_sub(1)
+            } else {
+                chars.len()
+            };
+            for position in finder_match.positions.iter().map(|p| p + 1) {
+                if position >= shown {
+                    break;
+                }
+                let x = chars[..position].iter().collect::<String>().width();
+                queue!(
+                    stdout,
+                    cursor::MoveTo::from(pos + (x, 0)),
+                    style::Print(chars[position])
+                )?;
+            }
+        }
+
+        if list_width < size.x {
+            let preview_left = top_left + (list_width + 1, 0);
+            let preview_width = size.x - list_width - 1;
+            queue!(stdout, SetStyle(self.theme.inactive_status_bar))?;
+            for y in 0..size.y {
+                queue!(
+                    stdout,
+                    cursor::MoveTo::from(top_left + (list_width, y)),
+                    style::Print('│')
+                )?;
+            }
+            queue!(stdout, SetStyle(self.theme.edit_zone))?;
+            let preview = finder.preview();
+            for y in 0..size.y {
+                let line = preview
+                    .get(y)
+                    .map_or(String::new(), |line| truncate_end(line, preview_width));
+                queue!(
+                    stdout,
+                    cursor::MoveTo::from(preview_left + (0, y)),
+                    style::Print(format!("{line:preview_width$}"))
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn display_tab_bar(&self, stdout: &mut Stdout) -> Result<()> {
+        let tab_bar = self.tab_bar();
+        queue!(
+            stdout,
+            SetStyle(self.theme.tab_bar),
+            cursor::MoveTo(0, 0),
+            style::Print(" ".repeat(self.window_size.x))
+        )?;
+        for tab in tab_bar.tabs.iter() {
+            let style = if tab.document == self.current {
+                self.theme.active_tab
+            } else {
+                self.theme.tab_bar
+            };
+            queue!(
+                stdout,
+                SetStyle(style),
+                cursor::MoveTo::from(Pos::new(tab.x, 0)),
+                style::Print(&tab.label)
+            )?;
+        }
+        queue!(stdout, SetStyle(self.theme.tab_bar))?;
+        if tab_bar.hidden_left {
+            queue!(stdout, cursor::MoveTo(0, 0), style::Print('<'))?;
+        }
+        if tab_bar.hidden_right {
+            queue!(
+                stdout,
+                cursor::MoveTo::from(Pos::new(self.window_size.x.saturating_sub(1), 0)),
+                style::Print('>')
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Prints the text, line numbers and status line of a window
+    fn display_window(&mut self, stdout: &mut Stdout, index: usize) -> Result<()> {
+        let window = self.windows[index].clone();
+        // The view of the focused window is the one of the buffer, the others
+        // move the buffer to theirs while they are printed
+        let focused_view = (index != self.focused).then(|| {
+            self.place_buffer(index);
+            let buffer = self.document_buffer_mut(window.document);
+            let focused_view = buffer.view();
+            buffer.set_view(window.view.clone());
+            focused_view
+        });
+
+        self.display_text(stdout, window.document)?;
+        if window.gutter_width > 0 {
+            self.display_gutter(stdout, &window)?;
+        }
+        if self.windows.len() > 1 {
+            let style = if index == self.focused {
+                self.theme.status_bar
+            } else {
+                self.theme.inactive_status_bar
+            };
+            let status = self
+                .config
+                .status_bar
+                .render(window.size.x, |name| self.status_value(index, name));
+            queue!(
+                stdout,
+                SetStyle(style),
+                cursor::MoveTo::from(window.top_left + (0, window.size.y.saturating_sub(1))),
+                style::Print(status)
+            )?;
+        }
+
+        if let Some(focused_view) = focused_view {
+            let view = self.document_buffer(window.document).view();
+            self.windows[index].view = view;
+            if window.document == self.current {
+                self.place_buffer(self.focused);
+                self.edit_buffer.set_view(focused_view);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the visible text of a document, as laid out in its window, over
+    /// every row of the window so that nothing from before is left
+    fn display_text(&mut self, stdout: &mut Stdout, document: usize) -> Result<()> {
+        let (buffer, highlighter) = if document == self.current {
+            // The diff of a swap file isn't in the language of the document
+            (
+                &mut self.edit_buffer,
+                self.highlighter
+                    .as_mut()
+                    .filter(|_| self.hidden_document.is_none()),
+            )
+        } else {
+            let document = &mut self.documents[document];
+            (&mut document.buffer, document.highlighter.as_mut())
+        };
+
+        queue!(stdout, SetStyle(self.theme.edit_zone))?;
+        let viewport_size = buffer.get_viewport_size();
+        let left = buffer.get_top_left_corner();
+        let rows = buffer.get_viewport();
+        for screen_y in 0..viewport_size.y {
+            let (indent, text) = match rows.get(screen_y) {
+                Some((pos, text)) => (pos.x - left.x, text.as_str()),
+                None => (0, ""),
+            };
+            let padding = viewport_size.x.saturating_sub(indent + text.width());
+            queue!(
+                stdout,
+                cursor::MoveTo::from(left + (0, screen_y)),
+                style::Print(format!("{:indent$}{text}{:padding$}", "", ""))
+            )?;
+        }
+
+        if let Some(highlighter) = highlighter {
+            if let Some(y) = buffer.take_changed_line() {
+                highlighter.invalidate_from(y);
+            }
+            let tokens: Vec<_> = buffer
+                .get_visible_lines()
+                .flat_map(|y| highlighter.highlight_line(buffer, y))
+                .collect();
+            for (pos, text, kind) in buffer.get_viewport_spans(&tokens) {
+                queue!(
+                    stdout,
+                    SetStyle(self.theme.syntax.get(kind).over(self.theme.edit_zone)),
+                    cursor::MoveTo::from(pos),
+                    style::Print(text)
+                )?;
+            }
+            queue!(stdout, SetStyle(self.theme.edit_zone))?;
+        }
+        Ok(())
+    }
+
+    /// Text of the placeholder `name` of the status line of a window
+    fn status_value(&self, window: usize, name: &str) -> Option<String> {
+        let index = self.windows[window].document;
+        let buffer = self.document_buffer(index);
+        let (filename, highlighter) = if index == self.current {
+            (&self.filename, &self.highlighter)
+        } else {
+            let document = &self.documents[index];
+            (&document.filename, &document.highlighter)
+        };
+        let cursor = buffer.get_cursor();
+        let lines = buffer.content_lines_len();
+        Some(match name {
+            "file" => match filename {
+                Some(filename) => filename.display().to_string(),
+                None => "[No Name]".to_string(),
+            },
+            "modified" => if buffer.is_modified() { " [+]" } else { "" }.to_string(),
+            "line" => (cursor.y + 1).to_string(),
+            "col" => (cursor.x + 1).to_string(),
+            "lines" => lines.to_string(),
+            "percent" => format!("{}%", (cursor.y + 1) * 100 / lines.max(1)),
+            "mode" => match self.state {
+                // The mode is the one of the focused window
+                _ if window != self.focused => "",
+                EditorState::EditMode => "EDIT",
+                EditorState::CommandMode => "COMMAND",
+                EditorState::SearchMode => "SEARCH",
+                EditorState::ConfirmMode => "CONFIRM",
+                EditorState::QuitMode => "QUIT",
+                EditorState::RecoverMode => "RECOVER",
+                EditorState::BufferListMode => "BUFFERS",
+                EditorState::FinderMode => "FIND",
+                EditorState::Init | EditorState::Close => "",
+            }
+            .to_string(),
+            "encoding" => "UTF-8".to_string(),
+            // Until saving makes them all the same
+            "line_ending" if buffer.has_mixed_line_endings() => {
+                format!("Mixed→{}", buffer.get_line_ending())
+            }
+            "line_ending" => buffer.get_line_ending().to_string(),
+            "filetype" => highlighter
+                .as_ref()
+                .map_or("Text", |highlighter| highlighter.grammar().name)
+                .to_string(),
+            "key" => self.last_keypress.clone(),
+            "buffer" => format!("{}/{}", index + 1, self.documents.len()),
+            _ => return None,
+        })
+    }
+
+    fn display_gutter(&self, stdout: &mut Stdout, window: &Window) -> Result<()> {
+        queue!(stdout, SetStyle(self.theme.gutter))?;
+        let buffer = self.document_buffer(window.document);
+        let number_width = window.gutter_width.saturating_sub(1);
+        let cursor_y = buffer.get_cursor().y;
+        let mut row_lines = buffer.get_visible_row_lines();
+        row_lines.resize(buffer.get_viewport_size().y, None);
+        for (screen_y, y) in row_lines.iter().enumerate() {
+            let number = match (y, self.config.line_numbers) {
+                (None, _) => String::new(),
+                (Some(y), LineNumbers::Relative) if *y != cursor_y => {
+                    y.abs_diff(cursor_y).to_string()
+                }
+                (Some(y), _) => (y + 1).to_string(),
+            };
+            queue!(
+                stdout,
+                cursor::MoveTo::from(window.top_left + (0, screen_y)),
+                style::Print(format!("{number:>number_width$} "))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Document open in the background, put back in the edit zone when switched
+/// to with its text, cursor and history
+#[derive(Debug, Clone, Default)]
+struct Document {
+    buffer: Buffer,
+    filename: Option<PathBuf>,
+    highlighter: Option<Highlighter>,
+    swap: SwapFile,
+}
+
+/// What asking about unsaved changes is about to close
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Closing {
+    Editor,
+    Document,
+}
+
+/// Line numbers shown in the gutter left of the edit zone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LineNumbers {
+    #[default]
+    None,
+    Absolute,
+    /// Distance to the cursor line, which shows its absolute number
+    Relative,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum EditorAction {
+    /// Quits, asking first whether to save unsaved changes
     Quit,
+    /// Quits without saving
+    ForceQuit,
     MoveUp,
     MoveDown,
     MoveRight,
@@ -492,12 +2164,28 @@ pub enum EditorAction {
     PageDown,
     MoveLineStart,
     MoveLineEnd,
+    SelectUp,
+    SelectDown,
+    SelectRight,
+    SelectLeft,
+    SelectLineStart,
+    SelectLineEnd,
     SaveDocument,
     DeleteCharBack,
     DeleteCharFront,
     DeleteAll,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
     GoIntoCommandMode,
     GoIntoEditMode,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    /// Completes the command name or file path before the cursor
+    Complete,
     Command(EditorCommand),
 }
 
@@ -505,6 +2193,70 @@ pub enum EditorAction {
 pub enum EditorCommand {
     SetFilename(String),
     SaveAs(String),
+    /// Opens a file in a new buffer, or switches to it if it is already open
+    Open(String),
+    /// Switches to the buffer with this number or file name
+    SwitchBuffer(String),
+    NextBuffer,
+    PreviousBuffer,
+    /// Closes the current buffer, asking first whether to save its changes
+    CloseBuffer,
+    /// Picks the buffer to switch to from a list in the command zone
+    ListBuffers,
+    /// Picks a file of the working directory to open
+    FindFile,
+    /// Splits the focused window in two views of its document
+    Split(SplitDirection),
+    CloseWindow,
+    NextWindow,
+    PreviousWindow,
+    /// Grows the focused window by `delta` rows, or columns when `direction`
+    /// is `Vertical`, shrinking it if negative
+    ResizeWindow {
+        direction: SplitDirection,
+        delta: isize,
+    },
+    /// Picks the register the next copy, cut or paste uses instead of the
+    /// default one
+    SelectRegister(char),
+    SetLineEnding(LineEnding),
+    /// Replaces the matches of a regular expression, `$1` or `${name}` in the
+    /// replacement are expanded to the capture groups
+    Substitute {
+        pattern: String,
+        replacement: String,
+        scope: SubstituteScope,
+        confirm: bool,
+    },
+}
+
+impl EditorCommand {
+    /// Names of the commands, as completed in the command zone
+    pub const NAMES: &[&str] = &[
+        "buffer",
+        "buffer_close",
+        "buffer_next",
+        "buffer_prev",
+        "buffers",
+        "edit",
+        "find_file",
+        "open",
+        "register",
+        "resize",
+        "save_as",
+        "set_filename",
+        "set_line_ending",
+        "split",
+        "substitute",
+        "vresize",
+        "vsplit",
+        "window_close",
+        "window_next",
+        "window_prev",
+    ];
+
+    /// Commands whose argument is a path
+    pub const PATH_NAMES: &[&str] = &["edit", "open", "save_as", "set_filename"];
 }
 
 impl FromStr for EditorCommand {
@@ -512,13 +2264,64 @@ impl FromStr for EditorCommand {
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let cmd = comma::parse_command(s).context("Unknown escape seq")?;
-        match cmd.get(0).context("No command")?.as_str() {
+        match cmd.first().context("No command")?.as_str() {
             "set_filename" => Ok(EditorCommand::SetFilename(
                 cmd.get(1).unwrap_or(&"".to_string()).clone(),
             )),
             "save_as" => Ok(EditorCommand::SaveAs(
                 cmd.get(1).context("No filename")?.to_string(),
             )),
+            "open" | "edit" => Ok(EditorCommand::Open(
+                cmd.get(1).context("No filename")?.to_string(),
+            )),
+            "buffer" | "b" => Ok(EditorCommand::SwitchBuffer(
+                cmd.get(1).context("No buffer")?.to_string(),
+            )),
+            "buffer_next" | "bn" => Ok(EditorCommand::NextBuffer),
+            "buffer_prev" | "bp" => Ok(EditorCommand::PreviousBuffer),
+            "buffer_close" | "bc" => Ok(EditorCommand::CloseBuffer),
+            "buffers" | "ls" => Ok(EditorCommand::ListBuffers),
+            "find_file" | "ff" => Ok(EditorCommand::FindFile),
+            "split" | "sp" => Ok(EditorCommand::Split(SplitDirection::Horizontal)),
+            "vsplit" | "vs" => Ok(EditorCommand::Split(SplitDirection::Vertical)),
+            "window_close" | "wc" => Ok(EditorCommand::CloseWindow),
+            "window_next" | "wn" => Ok(EditorCommand::NextWindow),
+            "window_prev" | "wp" => Ok(EditorCommand::PreviousWindow),
+            "resize" => Ok(EditorCommand::ResizeWindow {
+                direction: SplitDirection::Horizontal,
+                delta: cmd.get(1).context("No size")?.parse()?,
+            }),
+            "vresize" => Ok(EditorCommand::ResizeWindow {
+                direction: SplitDirection::Vertical,
+                delta: cmd.get(1).context("No size")?.parse()?,
+            }),
+            "register" | "reg" => {
+                let name = cmd.get(1).context("No register")?;
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(register), None) => Ok(EditorCommand::SelectRegister(register)),
+                    _ => Err(anyhow!("A register is named by a single character")),
+                }
+            }
+            "set_line_ending" => Ok(EditorCommand::SetLineEnding(LineEnding::from_str(
+                cmd.get(1).context("No line ending")?,
+            )?)),
+            "substitute" | "s" => {
+                let mut scope = SubstituteScope::default();
+                let mut confirm = false;
+                for option in cmd.iter().skip(3) {
+                    match option.as_str() {
+                        "confirm" => confirm = true,
+                        option => scope = SubstituteScope::from_str(option)?,
+                    }
+                }
+                Ok(EditorCommand::Substitute {
+                    pattern: cmd.get(1).context("No pattern")?.to_string(),
+                    replacement: cmd.get(2).context("No replacement")?.to_string(),
+                    scope,
+                    confirm,
+                })
+            }
             _ => Err(anyhow!("Unknown command")),
         }
     }
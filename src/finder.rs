@@ -0,0 +1,478 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Paths sent at once by the indexing thread
+const BATCH_SIZE: usize = 1000;
+/// Bytes of the selected file read for its preview
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 16;
+/// Match at the start of a path component
+const SEPARATOR_BONUS: i64 = 24;
+/// Match at the start of a word, after `_`, `-`, `.` or a space, or at an
+/// upper case letter following a lower case one
+const WORD_BONUS: i64 = 16;
+/// Match in the file name rather than in the directories leading to it
+const FILE_NAME_BONUS: i64 = 8;
+const GAP_START_PENALTY: i64 = 5;
+const GAP_EXTEND_PENALTY: i64 = 1;
+
+/// Files of a directory tree, listed in the background while they are
+/// searched. Files ignored by `.gitignore` and the like are left out, as are
+/// hidden ones.
+#[derive(Debug, Clone)]
+struct FileIndex {
+    /// Files found by the indexing thread and not taken yet
+    found: Arc<Mutex<Vec<String>>>,
+    /// Set by the indexing thread once it found every file
+    walked: Arc<AtomicBool>,
+    files: Vec<String>,
+    done: bool,
+}
+
+impl FileIndex {
+    fn new(root: PathBuf) -> FileIndex {
+        let found = Arc::new(Mutex::new(vec![]));
+        let walked = Arc::new(AtomicBool::new(false));
+        let (thread_found, thread_walked) = (Arc::clone(&found), Arc::clone(&walked));
+        thread::spawn(move || {
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            let walk = ignore::WalkBuilder::new(&root).require_git(false).build();
+            for entry in walk.flatten() {
+                if !entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file())
+                {
+                    continue;
+                }
+                let path = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                batch.push(path.to_string_lossy().into_owned());
+                if batch.len() == BATCH_SIZE {
+                    // Nobody is waiting for the files anymore
+                    if Arc::strong_count(&thread_found) == 1 {
+                        return;
+                    }
+                    thread_found.lock().unwrap().append(&mut batch);
+                }
+            }
+            thread_found.lock().unwrap().append(&mut batch);
+            thread_walked.store(true, Ordering::Release);
+        });
+        FileIndex {
+            found,
+            walked,
+            files: vec![],
+            done: false,
+        }
+    }
+
+    /// Takes the files found since the last call, returning the index of the
+    /// first one
+    fn poll(&mut self) -> usize {
+        let first_new = self.files.len();
+        if !self.done {
+            // Read before taking the files, so that none is left behind
+            let walked = self.walked.load(Ordering::Acquire);
+            self.files.append(&mut self.found.lock().unwrap());
+            self.done = walked;
+        }
+        first_new
+    }
+}
+
+/// File matching the query of the finder
+#[derive(Debug, Clone)]
+pub struct FinderMatch {
+    /// Index of the file in the index
+    file: usize,
+    score: i64,
+    /// Char indices of the characters of the path matching the query
+    pub positions: Vec<usize>,
+}
+
+/// Picks a file of a directory tree by typing some of the characters of its
+/// path, in order
+#[derive(Debug, Clone)]
+pub struct Finder {
+    root: PathBuf,
+    index: FileIndex,
+    query: String,
+    matches: Vec<FinderMatch>,
+    selected: usize,
+    /// Lines of the start of the selected file, once read
+    preview: Option<(usize, Vec<String>)>,
+}
+
+impl Finder {
+    /// Starts listing the files under `root`
+    pub fn new(root: PathBuf) -> Finder {
+        Finder {
+            index: FileIndex::new(root.clone()),
+            root,
+            query: String::new(),
+            matches: vec![],
+            selected: 0,
+            preview: None,
+        }
+    }
+
+    /// Matches the files found since the last call, returns whether there
+    /// were any
+    pub fn poll(&mut self) -> bool {
+        let first_new = self.index.poll();
+        if first_new == self.index.files.len() {
+            return false;
+        }
+        let selected = self.selected_match().map(|m| m.file);
+        let new_matches = self.match_files(first_new..self.index.files.len());
+        self.matches.extend(new_matches);
+        self.sort_matches();
+        // Keep the same file selected as better matches come in
+        if let Some(file) = selected {
+            self.selected = self
+                .matches
+                .iter()
+                .position(|m| m.file == file)
+                .unwrap_or_default();
+        }
+        true
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        !self.index.done
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        if query == self.query {
+            return;
+        }
+        // Files missing a character of the shorter query can't match this
+        // one, whatever its case
+        let narrowed = query.starts_with(&self.query);
+        self.query = query.to_string();
+        self.matches = if narrowed {
+            let matched: Vec<usize> = self.matches.iter().map(|m| m.file).collect();
+            self.match_files(matched)
+        } else {
+            self.match_files(0..self.index.files.len())
+        };
+        self.sort_matches();
+        self.selected = 0;
+    }
+
+    fn match_files(&self, files: impl IntoIterator<Item = usize>) -> Vec<FinderMatch> {
+        files
+            .into_iter()
+            .filter_map(|file| {
+                let (score, positions) = fuzzy_match(&self.query, &self.index.files[file])?;
+                Some(FinderMatch {
+                    file,
+                    score,
+                    positions,
+                })
+            })
+            .collect()
+    }
+
+    /// Best matches first, then shorter paths
+    fn sort_matches(&mut self) {
+        let files = &self.index.files;
+        self.matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(files[a.file].len().cmp(&files[b.file].len()))
+                .then(files[a.file].cmp(&files[b.file]))
+        });
+    }
+
+    /// Matching files as paths relative to the root, with the positions of
+    /// their matching characters
+    pub fn matches(&self) -> impl Iterator<Item = (&str, &FinderMatch)> {
+        self.matches
+            .iter()
+            .map(|m| (self.index.files[m.file].as_str(), m))
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.index.files.len()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    fn selected_match(&self) -> Option<&FinderMatch> {
+        self.matches.get(self.selected)
+    }
+
+    /// Moves the selection by `offset` matches, staying within the list
+    pub fn move_selection(&mut self, offset: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(offset)
+            .min(self.matches.len().saturating_sub(1));
+    }
+
+    /// Path of the selected file
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        self.selected_match()
+            .map(|m| self.root.join(&self.index.files[m.file]))
+    }
+
+    /// Reads the start of the selected file for its preview, unless it is
+    /// already read
+    pub fn load_preview(&mut self) {
+        let Some(file) = self.selected_match().map(|m| m.file) else {
+            return;
+        };
+        if self
+            .preview
+            .as_ref()
+            .is_none_or(|(shown, _)| *shown != file)
+        {
+            let lines = read_preview(&self.root.join(&self.index.files[file]));
+            self.preview = Some((file, lines));
+        }
+    }
+
+    /// First lines of the selected file, tabs expanded and control characters
+    /// replaced, once loaded
+    pub fn preview(&self) -> &[String] {
+        match (&self.preview, self.selected_match()) {
+            (Some((file, lines)), Some(selected)) if *file == selected.file => lines,
+            _ => &[],
+        }
+    }
+}
+
+fn read_preview(path: &Path) -> Vec<String> {
+    let mut bytes = vec![];
+    let read =
+        fs::File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+    if let Err(err) = read {
+        return vec![format!("Can't read the file : {err}")];
+    }
+    if bytes.contains(&0) {
+        return vec!["Binary file".to_string()];
+    }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(|line| {
+            line.replace('\t', "    ")
+                .chars()
+                .map(|c| if c.is_control() { '?' } else { c })
+                .collect()
+        })
+        .collect()
+}
+
+/// Score of `candidate` if every character of `query` appears in it in order,
+/// the higher the better, along with the char indices of the best characters
+/// to match. Matching is case insensitive unless `query` has capitals.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    if query.is_empty() {
+        return Some((0, vec![]));
+    }
+    let chars: Vec<char> = candidate.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+
+    // Cheap check before scoring
+    let mut rest = folded.iter();
+    if !query.iter().all(|q| rest.any(|c| c == q)) {
+        return None;
+    }
+
+    let file_name_start = chars
+        .iter()
+        .rposition(|c| *c == '/' || *c == '\\')
+        .map_or(0, |i| i + 1);
+    let bonus = |j: usize| {
+        let word_start = match j.checked_sub(1).map(|i| chars[i]) {
+            None | Some('/') | Some('\\') => SEPARATOR_BONUS,
+            Some('_' | '-' | '.' | ' ') => WORD_BONUS,
+            Some(previous) if previous.is_lowercase() && chars[j].is_uppercase() => WORD_BONUS,
+            _ => 0,
+        };
+        let file_name = if j >= file_name_start {
+            FILE_NAME_BONUS
+        } else {
+            0
+        };
+        MATCH_SCORE + word_start + file_name
+    };
+
+    // Best score with the query up to `i` matched and `query[i]` at `j`, and
+    // where `query[i - 1]` is then
+    let width = chars.len();
+    let mut scores = vec![None; query.len() * width];
+    let mut from = vec![0; query.len() * width];
+    for (j, c) in folded.iter().enumerate() {
+        if *c == query[0] {
+            scores[j] = Some(bonus(j));
+        }
+    }
+    for i in 1..query.len() {
+        // Best score of `query[i - 1]` at least two characters back, with
+        // the penalty of the gap since
+        let mut gap: Option<(i64, usize)> = None;
+        for j in 1..width {
+            if j >= 2 {
+                let previous =
+                    scores[(i - 1) * width + j - 2].map(|score| (score - GAP_START_PENALTY, j - 2));
+                gap = match (gap, previous) {
+                    (Some((score, k)), Some(previous))
+                        if score - GAP_EXTEND_PENALTY >= previous.0 =>
+                    {
+                        Some((score - GAP_EXTEND_PENALTY, k))
+                    }
+                    (Some((score, k)), None) => Some((score - GAP_EXTEND_PENALTY, k)),
+                    (_, previous) => previous,
+                };
+            }
+            if folded[j] != query[i] {
+                continue;
+            }
+            let consecutive =
+                scores[(i - 1) * width + j - 1].map(|score| (score + CONSECUTIVE_BONUS, j - 1));
+            let best = match (consecutive, gap) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((score, k)) = best {
+                scores[i * width + j] = Some(score + bonus(j));
+                from[i * width + j] = k;
+            }
+        }
+    }
+
+    let last = query.len() - 1;
+    let (mut j, score) = (0..width)
+        .filter_map(|j| scores[last * width + j].map(|score| (j, score)))
+        .max_by_key(|(j, score)| (*score, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        positions[i] = j;
+        j = from[i * width + j];
+    }
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &str) -> i64 {
+        fuzzy_match(query, candidate).unwrap().0
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert!(fuzzy_match("edr", "src/editor.rs").is_some());
+        assert!(fuzzy_match("rde", "src/editor.rs").is_none());
+        assert!(fuzzy_match("x", "src/editor.rs").is_none());
+        assert_eq!(fuzzy_match("", "src/editor.rs"), Some((0, vec![])));
+    }
+
+    #[test]
+    fn positions_are_char_indices() {
+        assert_eq!(
+            fuzzy_match("edr", "src/editor.rs").unwrap().1,
+            vec![4, 5, 11]
+        );
+        assert_eq!(fuzzy_match("ée", "é/ée").unwrap().1, vec![2, 3]);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("ed", "src/Editor.rs").is_some());
+        assert!(fuzzy_match("Ed", "src/Editor.rs").is_some());
+        assert!(fuzzy_match("Ed", "src/editor.rs").is_none());
+    }
+
+    #[test]
+    fn word_starts_rank_first() {
+        assert!(score("fb", "foo/bar.rs") > score("fb", "fabric.rs"));
+        assert!(score("ft", "file_type.rs") > score("ft", "left.rs"));
+        assert!(score("ft", "fileType.rs") > score("ft", "left.rs"));
+    }
+
+    #[test]
+    fn consecutive_characters_rank_first() {
+        assert!(score("edit", "src/editor.rs") > score("edit", "src/e_d_i_t.rs"));
+    }
+
+    #[test]
+    fn file_names_rank_before_directories() {
+        assert!(score("main", "src/main.rs") > score("main", "main/lib.rs"));
+    }
+
+    /// Finder over a new directory holding `files`, once every file is found
+    fn finder_over(name: &str, files: &[&str]) -> Finder {
+        let root = std::env::temp_dir().join(format!("rudit-finder-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let mut finder = Finder::new(root);
+        while finder.is_indexing() {
+            finder.poll();
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        finder
+    }
+
+    fn matched_paths(finder: &Finder) -> Vec<&str> {
+        finder.matches().map(|(path, _)| path).collect()
+    }
+
+    #[test]
+    fn growing_the_query_narrows_the_matches() {
+        let files = ["src/editor.rs", "src/Edit.rs", "docs/readme.md", "ed/x.rs"];
+        let mut finder = finder_over("narrow", &files);
+        let mut fresh = finder_over("fresh", &files);
+        for query in ["", "e", "ed", "edr", "edrs", "ed", "Ed", "Edi"] {
+            finder.set_query(query);
+            fresh.set_query("#");
+            fresh.set_query(query);
+            assert_eq!(matched_paths(&finder), matched_paths(&fresh), "{query}");
+        }
+        assert_eq!(matched_paths(&finder), vec!["src/Edit.rs"]);
+        let _ = fs::remove_dir_all(&finder.root);
+        let _ = fs::remove_dir_all(&fresh.root);
+    }
+
+    #[test]
+    fn best_positions_are_picked() {
+        // The `b` of `bar` is a better match than the one in `abc`
+        assert_eq!(fuzzy_match("b", "abc/bar").unwrap().1, vec![4]);
+        assert_eq!(fuzzy_match("rs", "src/rs.rs").unwrap().1, vec![4, 5]);
+    }
+}
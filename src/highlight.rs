@@ -0,0 +1,673 @@
+use std::{ops::Range, path::Path};
+
+use crate::{
+    buffer::{byte_to_grapheme, Buffer},
+    pos::Pos,
+};
+
+/// Kind of a highlighted token, each kind has its own colour in `Config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Comment,
+    String,
+    Number,
+    Keyword,
+    Type,
+    Constant,
+    Function,
+    Variable,
+    Key,
+    Heading,
+    Markup,
+}
+
+/// Lexer state carried over from the end of a line to the start of the next
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum LineState {
+    #[default]
+    Normal,
+    /// Inside a block comment, nested `depth` times
+    Comment { depth: usize },
+    /// Inside a multi-line string opened by the string rule at this index
+    String(usize),
+    /// Inside a fenced Markdown code block
+    CodeBlock,
+}
+
+/// Tokens of a single line, as byte ranges
+type Tokens = Vec<(Range<usize>, TokenKind)>;
+
+/// A language, recognised from the extension or name of a file
+#[derive(Debug)]
+pub struct Grammar {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    file_names: &'static [&'static str],
+    rules: Rules,
+}
+
+#[derive(Debug)]
+enum Rules {
+    Code(CodeRules),
+    Markdown,
+}
+
+#[derive(Debug)]
+struct StringRule {
+    open: &'static str,
+    close: &'static str,
+    multiline: bool,
+    escapes: bool,
+}
+
+/// What makes a word or string a key rather than a value
+#[derive(Debug, PartialEq, Eq)]
+enum Keys {
+    None,
+    /// `key = value`, including dotted keys
+    BeforeEquals,
+    /// `"key": value`
+    BeforeColon,
+}
+
+/// Description of a programming or configuration language, enough to tell
+/// its comments, literals and keywords apart
+#[derive(Debug)]
+struct CodeRules {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    nested_comments: bool,
+    /// Tried in order, so longer delimiters have to come first
+    strings: &'static [StringRule],
+    char_literals: bool,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    constants: &'static [&'static str],
+    /// Words starting with an uppercase letter are types
+    capitalized_types: bool,
+    /// Words followed by `!` are macro calls
+    macros: bool,
+    /// `$name`, `${name}` and `$1` style variables
+    variables: bool,
+    keys: Keys,
+    /// Lines starting with `[` are table headers
+    table_headers: bool,
+    /// Chars allowed in words besides alphanumerics and `_`
+    word_chars: &'static str,
+}
+
+const RUST: Grammar = Grammar {
+    name: "Rust",
+    extensions: &["rs"],
+    file_names: &[],
+    rules: Rules::Code(CodeRules {
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        nested_comments: true,
+        strings: &[
+            StringRule {
+                open: "r#\"",
+                close: "\"#",
+                multiline: true,
+                escapes: false,
+            },
+            StringRule {
+                open: "r\"",
+                close: "\"",
+                multiline: true,
+                escapes: false,
+            },
+            StringRule {
+                open: "b\"",
+                close: "\"",
+                multiline: true,
+                escapes: true,
+            },
+            StringRule {
+                open: "\"",
+                close: "\"",
+                multiline: true,
+                escapes: true,
+            },
+        ],
+        char_literals: true,
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+            "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "type",
+            "unsafe", "use", "where", "while",
+        ],
+        types: &[
+            "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "str", "u8",
+            "u16", "u32", "u64", "u128", "usize",
+        ],
+        constants: &["true", "false", "None", "Some", "Ok", "Err"],
+        capitalized_types: true,
+        macros: true,
+        variables: false,
+        keys: Keys::None,
+        table_headers: false,
+        word_chars: "",
+    }),
+};
+
+const TOML: Grammar = Grammar {
+    name: "TOML",
+    extensions: &["toml"],
+    file_names: &["Cargo.lock"],
+    rules: Rules::Code(CodeRules {
+        line_comments: &["#"],
+        block_comment: None,
+        nested_comments: false,
+        strings: &[
+            StringRule {
+                open: "\"\"\"",
+                close: "\"\"\"",
+                multiline: true,
+                escapes: true,
+            },
+            StringRule {
+                open: "'''",
+                close: "'''",
+                multiline: true,
+                escapes: false,
+            },
+            StringRule {
+                open: "\"",
+                close: "\"",
+                multiline: false,
+                escapes: true,
+            },
+            StringRule {
+                open: "'",
+                close: "'",
+                multiline: false,
+                escapes: false,
+            },
+        ],
+        char_literals: false,
+        keywords: &[],
+        types: &[],
+        constants: &["true", "false", "inf", "nan"],
+        capitalized_types: false,
+        macros: false,
+        variables: false,
+        keys: Keys::BeforeEquals,
+        table_headers: true,
+        word_chars: "-",
+    }),
+};
+
+const JSON: Grammar = Grammar {
+    name: "JSON",
+    extensions: &["json"],
+    file_names: &[],
+    rules: Rules::Code(CodeRules {
+        line_comments: &[],
+        block_comment: None,
+        nested_comments: false,
+        strings: &[StringRule {
+            open: "\"",
+            close: "\"",
+            multiline: false,
+            escapes: true,
+        }],
+        char_literals: false,
+        keywords: &[],
+        types: &[],
+        constants: &["true", "false", "null"],
+        capitalized_types: false,
+        macros: false,
+        variables: false,
+        keys: Keys::BeforeColon,
+        table_headers: false,
+        word_chars: "",
+    }),
+};
+
+const SHELL: Grammar = Grammar {
+    name: "Shell",
+    extensions: &["sh", "bash", "zsh"],
+    file_names: &[".bashrc", ".bash_profile", ".profile", ".zshrc"],
+    rules: Rules::Code(CodeRules {
+        line_comments: &["#"],
+        block_comment: None,
+        nested_comments: false,
+        strings: &[
+            StringRule {
+                open: "\"",
+                close: "\"",
+                multiline: true,
+                escapes: true,
+            },
+            StringRule {
+                open: "'",
+                close: "'",
+                multiline: true,
+                escapes: false,
+            },
+        ],
+        char_literals: false,
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "readonly", "return", "select", "then", "until", "while",
+        ],
+        types: &[],
+        constants: &["true", "false"],
+        capitalized_types: false,
+        macros: false,
+        variables: true,
+        keys: Keys::None,
+        table_headers: false,
+        word_chars: "",
+    }),
+};
+
+const MARKDOWN: Grammar = Grammar {
+    name: "Markdown",
+    extensions: &["md", "markdown"],
+    file_names: &[],
+    rules: Rules::Markdown,
+};
+
+const GRAMMARS: &[&Grammar] = &[&RUST, &TOML, &JSON, &SHELL, &MARKDOWN];
+
+impl Grammar {
+    /// Grammar of the file at `path`, from its extension or its name
+    pub fn for_path(path: &Path) -> Option<&'static Grammar> {
+        let file_name = path.file_name()?.to_str()?;
+        let extension = path.extension().and_then(|e| e.to_str());
+        GRAMMARS.iter().copied().find(|grammar| {
+            grammar.file_names.contains(&file_name)
+                || extension.is_some_and(|e| grammar.extensions.contains(&e))
+        })
+    }
+
+    fn lex(&self, line: &str, state: LineState, tokens: &mut Tokens) -> LineState {
+        match &self.rules {
+            Rules::Code(rules) => rules.lex(line, state, tokens),
+            Rules::Markdown => lex_markdown(line, state, tokens),
+        }
+    }
+}
+
+/// Tokenizes the lines of a buffer on demand, remembering the state each line
+/// starts in so that only lines after an edit have to be lexed again
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    grammar: &'static Grammar,
+    /// State at the start of each line lexed so far, the first line always
+    /// starts in the normal state
+    line_states: Vec<LineState>,
+}
+
+impl Highlighter {
+    pub fn new(grammar: &'static Grammar) -> Highlighter {
+        Highlighter {
+            grammar,
+            line_states: vec![LineState::Normal],
+        }
+    }
+
+    /// Highlighter for the file at `path`, if its language is known
+    pub fn for_path(path: &Path) -> Option<Highlighter> {
+        Grammar::for_path(path).map(Highlighter::new)
+    }
+
+    pub fn grammar(&self) -> &'static Grammar {
+        self.grammar
+    }
+
+    /// Forgets the states of the lines after line `y`, which has been edited
+    pub fn invalidate_from(&mut self, y: usize) {
+        self.line_states.truncate(y + 1);
+    }
+
+    /// Tokens of line `y`, as `(start, end, kind)` with grapheme positions
+    pub fn highlight_line(&mut self, buffer: &Buffer, y: usize) -> Vec<(Pos, Pos, TokenKind)> {
+        let mut tokens = vec![];
+        while self.line_states.len() <= y {
+            let line_y = self.line_states.len() - 1;
+            let Some(line) = buffer.line(line_y) else {
+                return vec![];
+            };
+            let state = self
+                .grammar
+                .lex(&line, self.line_states[line_y], &mut tokens);
+            self.line_states.push(state);
+            tokens.clear();
+        }
+        let Some(line) = buffer.line(y) else {
+            return vec![];
+        };
+        self.grammar.lex(&line, self.line_states[y], &mut tokens);
+        tokens
+            .into_iter()
+            .filter(|(range, _)| !range.is_empty())
+            .map(|(range, kind)| {
+                (
+                    Pos::new(byte_to_grapheme(&line, range.start), y),
+                    Pos::new(byte_to_grapheme(&line, range.end), y),
+                    kind,
+                )
+            })
+            .collect()
+    }
+}
+
+impl CodeRules {
+    fn lex(&self, line: &str, mut state: LineState, tokens: &mut Tokens) -> LineState {
+        // Finish the comment or string left open by the previous line
+        let mut i = match state {
+            LineState::Comment { depth } => {
+                let (end, depth) = self.scan_block_comment(line, 0, depth);
+                tokens.push((0..end, TokenKind::Comment));
+                state = if depth == 0 {
+                    LineState::Normal
+                } else {
+                    LineState::Comment { depth }
+                };
+                end
+            }
+            LineState::String(rule) => {
+                let (end, closed) = scan_string(line, 0, &self.strings[rule]);
+                tokens.push((0..end, TokenKind::String));
+                if closed {
+                    state = LineState::Normal;
+                }
+                end
+            }
+            _ => 0,
+        };
+
+        while i < line.len() {
+            let rest = &line[i..];
+            let c = rest.chars().next().unwrap_or_default();
+
+            if c.is_whitespace() {
+                i += c.len_utf8();
+                continue;
+            }
+
+            if self.line_comments.iter().any(|p| rest.starts_with(p)) {
+                tokens.push((i..line.len(), TokenKind::Comment));
+                break;
+            }
+
+            if let Some((open, _)) = self
+                .block_comment
+                .filter(|(open, _)| rest.starts_with(open))
+            {
+                let (end, depth) = self.scan_block_comment(line, i + open.len(), 1);
+                tokens.push((i..end, TokenKind::Comment));
+                if depth > 0 {
+                    state = LineState::Comment { depth };
+                }
+                i = end;
+                continue;
+            }
+
+            if let Some((index, rule)) = self
+                .strings
+                .iter()
+                .enumerate()
+                .find(|(_, rule)| rest.starts_with(rule.open))
+            {
+                let (end, closed) = scan_string(line, i + rule.open.len(), rule);
+                let kind = if closed && self.is_key(&line[end..]) {
+                    TokenKind::Key
+                } else {
+                    TokenKind::String
+                };
+                tokens.push((i..end, kind));
+                if !closed && rule.multiline {
+                    state = LineState::String(index);
+                }
+                i = end;
+                continue;
+            }
+
+            if self.char_literals && c == '\'' {
+                if let Some(len) = char_literal_len(rest) {
+                    tokens.push((i..i + len, TokenKind::String));
+                    i += len;
+                    continue;
+                }
+            }
+
+            if c.is_ascii_digit() {
+                let len = number_len(rest);
+                tokens.push((i..i + len, TokenKind::Number));
+                i += len;
+                continue;
+            }
+
+            if self.variables && c == '$' {
+                if let Some(len) = variable_len(rest) {
+                    tokens.push((i..i + len, TokenKind::Variable));
+                    i += len;
+                    continue;
+                }
+            }
+
+            if self.table_headers && c == '[' && line[..i].trim().is_empty() {
+                let end = rest.rfind(']').map(|e| i + e + 1).unwrap_or(line.len());
+                tokens.push((i..end, TokenKind::Heading));
+                i = end;
+                continue;
+            }
+
+            if c.is_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| {
+                        !(c.is_alphanumeric() || c == '_' || self.word_chars.contains(c))
+                    })
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                if let Some(kind) = self.word_kind(word, &rest[len..]) {
+                    tokens.push((i..i + len, kind));
+                }
+                i += len;
+                continue;
+            }
+
+            i += c.len_utf8();
+        }
+        state
+    }
+
+    fn word_kind(&self, word: &str, after: &str) -> Option<TokenKind> {
+        if self.is_key(after) || (self.keys == Keys::BeforeEquals && after.starts_with('.')) {
+            Some(TokenKind::Key)
+        } else if self.keywords.contains(&word) {
+            Some(TokenKind::Keyword)
+        } else if self.types.contains(&word) {
+            Some(TokenKind::Type)
+        } else if self.constants.contains(&word) {
+            Some(TokenKind::Constant)
+        } else if self.capitalized_types && word.starts_with(char::is_uppercase) {
+            Some(TokenKind::Type)
+        } else if after.starts_with('(')
+            || (self.macros && after.starts_with('!') && !after.starts_with("!="))
+        {
+            Some(TokenKind::Function)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a word or string followed by `after` is a key
+    fn is_key(&self, after: &str) -> bool {
+        let after = after.trim_start();
+        match self.keys {
+            Keys::None => false,
+            Keys::BeforeEquals => after.starts_with('=') && !after.starts_with("=="),
+            Keys::BeforeColon => after.starts_with(':'),
+        }
+    }
+
+    /// End of the block comment going on at `from` with `depth` levels of
+    /// nesting, along with the depth left at that point
+    fn scan_block_comment(&self, line: &str, from: usize, mut depth: usize) -> (usize, usize) {
+        let Some((open, close)) = self.block_comment else {
+            return (from, 0);
+        };
+        let mut i = from;
+        while i < line.len() {
+            let rest = &line[i..];
+            if rest.starts_with(close) {
+                i += close.len();
+                depth -= 1;
+                if depth == 0 {
+                    return (i, 0);
+                }
+            } else if self.nested_comments && rest.starts_with(open) {
+                i += open.len();
+                depth += 1;
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        (line.len(), depth)
+    }
+}
+
+/// End of the string going on at `from`, and whether it is closed there
+fn scan_string(line: &str, from: usize, rule: &StringRule) -> (usize, bool) {
+    let mut chars = line[from..].char_indices();
+    while let Some((offset, c)) = chars.next() {
+        if rule.escapes && c == '\\' {
+            chars.next();
+        } else if line[from + offset..].starts_with(rule.close) {
+            return (from + offset + rule.close.len(), true);
+        }
+    }
+    (line.len(), false)
+}
+
+/// Length of the char literal `rest` starts with, `None` for a lifetime
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let body = rest.get(1..)?;
+    let len = if body.starts_with('\\') {
+        body.get(2..)?.find('\'')? + 3
+    } else {
+        let c = body.chars().next()?;
+        body[c.len_utf8()..]
+            .starts_with('\'')
+            .then_some(c.len_utf8() + 1)?
+    };
+    Some(1 + len)
+}
+
+/// Length of the number `rest` starts with, including its suffix, but not a
+/// following `..` range operator
+fn number_len(rest: &str) -> usize {
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let part_of_number = c.is_alphanumeric()
+            || c == '_'
+            || (c == '.' && chars.peek().is_some_and(|(_, next)| next.is_ascii_digit()));
+        if !part_of_number {
+            return i;
+        }
+    }
+    rest.len()
+}
+
+/// Length of the shell variable `rest` starts with
+fn variable_len(rest: &str) -> Option<usize> {
+    let name = &rest[1..];
+    if name.starts_with('{') {
+        return Some(name.find('}').map_or(rest.len(), |end| end + 2));
+    }
+    let len = name
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(name.len());
+    if len > 0 {
+        Some(len + 1)
+    } else if name.starts_with(['@', '*', '#', '?', '$', '!', '-']) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn lex_markdown(line: &str, state: LineState, tokens: &mut Tokens) -> LineState {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+
+    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        tokens.push((0..line.len(), TokenKind::String));
+        return if state == LineState::CodeBlock {
+            LineState::Normal
+        } else {
+            LineState::CodeBlock
+        };
+    }
+    if state == LineState::CodeBlock {
+        tokens.push((0..line.len(), TokenKind::String));
+        return LineState::CodeBlock;
+    }
+
+    let hashes = trimmed.len() - trimmed.trim_start_matches('#').len();
+    if (1..=6).contains(&hashes) && (trimmed.len() == hashes || trimmed[hashes..].starts_with(' '))
+    {
+        tokens.push((0..line.len(), TokenKind::Heading));
+        return LineState::Normal;
+    }
+
+    let mut i = indent;
+    if trimmed.starts_with('>') {
+        tokens.push((i..i + 1, TokenKind::Keyword));
+        i += 1;
+    } else if let Some(len) = list_marker_len(trimmed) {
+        tokens.push((i..i + len, TokenKind::Keyword));
+        i += len;
+    }
+
+    while i < line.len() {
+        let rest = &line[i..];
+        let c = rest.chars().next().unwrap_or_default();
+        let len = match c {
+            '`' => rest[1..].find('`').map(|end| end + 2),
+            '*' | '_' if c == '*' || !line[..i].ends_with(char::is_alphanumeric) => {
+                let run = rest.len() - rest.trim_start_matches(c).len();
+                let marker = &rest[..run.min(3)];
+                rest[marker.len()..]
+                    .find(marker)
+                    .filter(|&end| end > 0)
+                    .map(|end| end + 2 * marker.len())
+            }
+            '[' => rest
+                .find("](")
+                .and_then(|middle| rest[middle..].find(')').map(|end| middle + end + 1)),
+            _ => None,
+        };
+        match len {
+            Some(len) => {
+                let kind = if c == '`' {
+                    TokenKind::String
+                } else {
+                    TokenKind::Markup
+                };
+                tokens.push((i..i + len, kind));
+                i += len;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    LineState::Normal
+}
+
+/// Length of the bullet or number starting a list item, with its space
+fn list_marker_len(trimmed: &str) -> Option<usize> {
+    if ["- ", "* ", "+ "].iter().any(|m| trimmed.starts_with(m)) {
+        return Some(1);
+    }
+    let digits = trimmed.len()
+        - trimmed
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+    let after = &trimmed[digits..];
+    (digits > 0 && (after.starts_with(". ") || after.starts_with(") "))).then_some(digits + 1)
+}
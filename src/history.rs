@@ -0,0 +1,332 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::pos::Pos;
+
+/// A single invertible change to the text of a buffer, indexed in chars
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Edit {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete {
+                at: *at,
+                text: text.clone(),
+            },
+            Edit::Delete { at, text } => Edit::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+        }
+    }
+
+    /// Whether this edit is what a single keystroke produces, the only kind
+    /// of edit merged into the previous transaction
+    fn is_single_grapheme(&self) -> bool {
+        let (Edit::Insert { text, .. } | Edit::Delete { text, .. }) = self;
+        text.graphemes(true).count() == 1
+    }
+}
+
+/// Edits undone and redone as one step, with the cursor to restore on each side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// Unique among the transactions of a history
+    pub id: u64,
+    pub edits: Vec<Edit>,
+    pub cursor_before: Pos,
+    pub cursor_after: Pos,
+}
+
+impl Transaction {
+    fn new(id: u64, cursor: Pos) -> Transaction {
+        Transaction {
+            id,
+            edits: vec![],
+            cursor_before: cursor,
+            cursor_after: cursor,
+        }
+    }
+
+    /// Merges `edit` into the last edit if it continues it, i.e. typing right
+    /// after the last insert, or deleting right before or after the last delete
+    fn absorb(&mut self, edit: &Edit) -> bool {
+        match (self.edits.last_mut(), edit) {
+            (
+                Some(Edit::Insert { at, text }),
+                Edit::Insert {
+                    at: new_at,
+                    text: new_text,
+                },
+            ) if *at + text.chars().count() == *new_at => {
+                text.push_str(new_text);
+                true
+            }
+            (
+                Some(Edit::Delete { at, text }),
+                Edit::Delete {
+                    at: new_at,
+                    text: new_text,
+                },
+            ) => {
+                if *new_at == *at {
+                    text.push_str(new_text);
+                    true
+                } else if *new_at + new_text.chars().count() == *at {
+                    text.insert_str(0, new_text);
+                    *at = *new_at;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    group_depth: usize,
+    /// Whether the next keystroke edit may be merged into the last transaction
+    can_merge: bool,
+    last_id: u64,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// Starts grouping every recorded edit into a single transaction until the
+    /// matching `end_group`, groups can be nested
+    pub fn begin_group(&mut self, cursor: Pos) {
+        if self.group_depth == 0 {
+            let transaction = self.new_transaction(cursor);
+            self.undo_stack.push(transaction);
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            if self.undo_stack.last().is_some_and(|t| t.edits.is_empty()) {
+                self.undo_stack.pop();
+            }
+            self.can_merge = false;
+        }
+    }
+
+    pub fn record(&mut self, edit: Edit, cursor_before: Pos, cursor_after: Pos) {
+        self.redo_stack.clear();
+
+        if self.group_depth > 0 {
+            if let Some(transaction) = self.undo_stack.last_mut() {
+                transaction.edits.push(edit);
+                transaction.cursor_after = cursor_after;
+                return;
+            }
+        }
+
+        let single_grapheme = edit.is_single_grapheme();
+        if self.can_merge && single_grapheme {
+            if let Some(transaction) = self.undo_stack.last_mut() {
+                if transaction.absorb(&edit) {
+                    transaction.cursor_after = cursor_after;
+                    return;
+                }
+            }
+        }
+
+        let mut transaction = self.new_transaction(cursor_before);
+        transaction.edits.push(edit);
+        transaction.cursor_after = cursor_after;
+        self.undo_stack.push(transaction);
+        self.can_merge = single_grapheme;
+    }
+
+    fn new_transaction(&mut self, cursor: Pos) -> Transaction {
+        self.last_id += 1;
+        Transaction::new(self.last_id, cursor)
+    }
+
+    /// Identifies the state of the text reached through the recorded edits,
+    /// it is the same again whenever undo or redo come back to that state
+    pub fn revision(&self) -> u64 {
+        self.undo_stack.last().map_or(0, |t| t.id)
+    }
+
+    /// Keeps the next keystroke edit out of the last transaction, so that the
+    /// current revision stops matching the text as soon as it changes
+    pub fn seal(&mut self) {
+        self.can_merge = false;
+    }
+
+    /// Pops the last transaction, the caller is responsible for applying the
+    /// inverse of its edits in reverse order
+    pub fn undo(&mut self) -> Option<Transaction> {
+        let transaction = self.undo_stack.pop()?;
+        self.redo_stack.push(transaction.clone());
+        self.can_merge = false;
+        Some(transaction)
+    }
+
+    /// Pops the last undone transaction, the caller is responsible for
+    /// applying its edits in order
+    pub fn redo(&mut self) -> Option<Transaction> {
+        let transaction = self.redo_stack.pop()?;
+        self.undo_stack.push(transaction.clone());
+        self.can_merge = false;
+        Some(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(at: usize, text: &str) -> Edit {
+        Edit::Insert {
+            at,
+            text: text.to_string(),
+        }
+    }
+
+    fn delete(at: usize, text: &str) -> Edit {
+        Edit::Delete {
+            at,
+            text: text.to_string(),
+        }
+    }
+
+    /// Records `edit` with cursors on the first line at the given columns
+    fn record(history: &mut History, edit: Edit, before: usize, after: usize) {
+        history.record(edit, Pos::new(before, 0), Pos::new(after, 0));
+    }
+
+    #[test]
+    fn typing_merges_into_one_transaction() {
+        let mut history = History::new();
+        record(&mut history, insert(0, "a"), 0, 1);
+        record(&mut history, insert(1, "b"), 1, 2);
+        record(&mut history, insert(2, "c"), 2, 3);
+        let transaction = history.undo().unwrap();
+        assert_eq!(transaction.edits, vec![insert(0, "abc")]);
+        assert_eq!(transaction.cursor_before, Pos::new(0, 0));
+        assert_eq!(transaction.cursor_after, Pos::new(3, 0));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn typing_elsewhere_starts_a_new_transaction() {
+        let mut history = History::new();
+        record(&mut history, insert(0, "a"), 0, 1);
+        record(&mut history, insert(5, "b"), 5, 6);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(5, "b")]);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(0, "a")]);
+    }
+
+    #[test]
+    fn deletions_merge_backwards_and_forwards() {
+        let mut history = History::new();
+        // Backspace from the end of "abc"
+        record(&mut history, delete(2, "c"), 3, 2);
+        record(&mut history, delete(1, "b"), 2, 1);
+        assert_eq!(history.undo().unwrap().edits, vec![delete(1, "bc")]);
+
+        // Delete in front of the cursor
+        record(&mut history, delete(0, "a"), 0, 0);
+        record(&mut history, delete(0, "b"), 0, 0);
+        assert_eq!(history.undo().unwrap().edits, vec![delete(0, "ab")]);
+    }
+
+    #[test]
+    fn pastes_are_not_merged() {
+        let mut history = History::new();
+        record(&mut history, insert(0, "a"), 0, 1);
+        record(&mut history, insert(1, "pasted"), 1, 7);
+        record(&mut history, insert(7, "b"), 7, 8);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(7, "b")]);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(1, "pasted")]);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(0, "a")]);
+    }
+
+    #[test]
+    fn a_combining_mark_is_a_keystroke() {
+        let mut history = History::new();
+        record(&mut history, insert(0, "e"), 0, 1);
+        record(&mut history, insert(1, "\u{301}"), 1, 1);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(0, "e\u{301}")]);
+    }
+
+    #[test]
+    fn groups_are_undone_as_one_step() {
+        let mut history = History::new();
+        history.begin_group(Pos::new(4, 0));
+        record(&mut history, delete(0, "abcd"), 4, 0);
+        history.begin_group(Pos::new(0, 0));
+        record(&mut history, insert(0, "x"), 0, 1);
+        history.end_group();
+        record(&mut history, insert(1, "y"), 1, 2);
+        history.end_group();
+        // Typing after the group starts a new transaction
+        record(&mut history, insert(2, "z"), 2, 3);
+
+        assert_eq!(history.undo().unwrap().edits, vec![insert(2, "z")]);
+        let group = history.undo().unwrap();
+        assert_eq!(
+            group.edits,
+            vec![delete(0, "abcd"), insert(0, "x"), insert(1, "y")]
+        );
+        assert_eq!(group.cursor_before, Pos::new(4, 0));
+        assert_eq!(group.cursor_after, Pos::new(2, 0));
+    }
+
+    #[test]
+    fn empty_groups_leave_no_transaction() {
+        let mut history = History::new();
+        history.begin_group(Pos::default());
+        history.end_group();
+        assert!(history.undo().is_none());
+        assert_eq!(history.revision(), 0);
+    }
+
+    #[test]
+    fn redo_replays_what_was_undone() {
+        let mut history = History::new();
+        record(&mut history, insert(0, "a"), 0, 1);
+        let first = history.revision();
+        record(&mut history, insert(5, "b"), 5, 6);
+        let second = history.revision();
+
+        history.undo();
+        assert_eq!(history.revision(), first);
+        assert_eq!(history.redo().unwrap().edits, vec![insert(5, "b")]);
+        assert_eq!(history.revision(), second);
+        assert!(history.redo().is_none());
+
+        // A new edit drops what was undone
+        history.undo();
+        record(&mut history, insert(1, "c"), 1, 2);
+        assert!(history.redo().is_none());
+        assert_ne!(history.revision(), second);
+    }
+
+    #[test]
+    fn undo_and_seal_stop_merging() {
+        let mut history = History::new();
+        record(&mut history, insert(0, "a"), 0, 1);
+        history.seal();
+        record(&mut history, insert(1, "b"), 1, 2);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(1, "b")]);
+        record(&mut history, insert(1, "c"), 1, 2);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(1, "c")]);
+        assert_eq!(history.undo().unwrap().edits, vec![insert(0, "a")]);
+    }
+}
@@ -1,5 +1,16 @@
 pub mod buffer;
+pub mod clipboard;
 pub mod color;
+pub mod completion;
 pub mod config;
 pub mod editor;
+pub mod finder;
+pub mod highlight;
+pub mod history;
 pub mod pos;
+pub mod search;
+pub mod status;
+pub mod swap;
+pub mod tab_bar;
+pub mod theme;
+pub mod window;
@@ -46,7 +46,11 @@ fn main() -> Result<()> {
             terminal::enable_raw_mode()?;
 
             while !editor.is_done() {
-                editor.process_event(event::read()?)?;
+                // Wake up regularly to keep the swap file up to date
+                if event::poll(editor.event_timeout())? {
+                    editor.process_event(event::read()?)?;
+                }
+                editor.update_swap_file();
                 editor.display(&mut stdout)?;
             }
 
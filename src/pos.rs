@@ -46,8 +46,8 @@ impl Sub for Pos {
 
     fn sub(self, rhs: Self) -> Self::Output {
         Pos {
-            x: self.x.checked_sub(rhs.x).unwrap_or_default(),
-            y: self.y.checked_sub(rhs.y).unwrap_or_default(),
+            x: self.x.saturating_sub(rhs.x),
+            y: self.y.saturating_sub(rhs.y),
         }
     }
 }
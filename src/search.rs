@@ -0,0 +1,379 @@
+use std::{collections::VecDeque, ops::Range, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buffer::{byte_to_grapheme, grapheme_to_byte, Buffer},
+    pos::Pos,
+};
+
+/// Matches of a pattern in a buffer, as `(start, end)` grapheme positions
+#[derive(Debug, Clone)]
+pub struct Search {
+    regex: Regex,
+}
+
+impl Search {
+    /// Search for the literal text `query`
+    pub fn new(query: &str, case_sensitive: bool) -> Result<Search> {
+        Search::from_pattern(&regex::escape(query), case_sensitive)
+    }
+
+    /// Search for the regular expression `pattern`
+    pub fn from_pattern(pattern: &str, case_sensitive: bool) -> Result<Search> {
+        Ok(Search {
+            regex: RegexBuilder::new(pattern)
+                .case_insensitive(!case_sensitive)
+                .build()?,
+        })
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// Every non-empty match on line `y`, in order
+    pub fn matches_in_line(&self, buffer: &Buffer, y: usize) -> Vec<(Pos, Pos)> {
+        let Some(line) = buffer.line(y) else {
+            return vec![];
+        };
+        self.regex
+            .find_iter(&line)
+            .filter(|m| !m.is_empty())
+            .map(|m| {
+                (
+                    Pos::new(byte_to_grapheme(&line, m.start()), y),
+                    Pos::new(byte_to_grapheme(&line, m.end()), y),
+                )
+            })
+            .collect()
+    }
+
+    /// Every match on the lines in `lines`, in order
+    pub fn matches_in_lines(&self, buffer: &Buffer, lines: Range<usize>) -> Vec<(Pos, Pos)> {
+        lines
+            .flat_map(|y| self.matches_in_line(buffer, y))
+            .collect()
+    }
+
+    /// First match starting at or after `from`, looking again from the start
+    /// of the buffer if `wrap_around` is set
+    pub fn next(&self, buffer: &Buffer, from: Pos, wrap_around: bool) -> Option<(Pos, Pos)> {
+        let after_from = (from.y..buffer.content_lines_len()).find_map(|y| {
+            self.matches_in_line(buffer, y)
+                .into_iter()
+                .find(|(start, _)| y > from.y || start.x >= from.x)
+        });
+        if after_from.is_some() || !wrap_around {
+            return after_from;
+        }
+        (0..=from.y).find_map(|y| {
+            self.matches_in_line(buffer, y)
+                .into_iter()
+                .find(|(start, _)| y < from.y || start.x < from.x)
+        })
+    }
+
+    /// Last match starting strictly before `before`, looking again from the end
+    /// of the buffer if `wrap_around` is set
+    pub fn previous(&self, buffer: &Buffer, before: Pos, wrap_around: bool) -> Option<(Pos, Pos)> {
+        let lines_len = buffer.content_lines_len();
+        let before_pos = (0..=before.y.min(lines_len.saturating_sub(1)))
+            .rev()
+            .find_map(|y| {
+                self.matches_in_line(buffer, y)
+                    .into_iter()
+                    .rev()
+                    .find(|(start, _)| y < before.y || start.x < before.x)
+            });
+        if before_pos.is_some() || !wrap_around {
+            return before_pos;
+        }
+        (before.y..lines_len).rev().find_map(|y| {
+            self.matches_in_line(buffer, y)
+                .into_iter()
+                .rev()
+                .find(|(start, _)| y > before.y || start.x >= before.x)
+        })
+    }
+}
+
+/// Part of the buffer a substitution applies to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SubstituteScope {
+    #[default]
+    Line,
+    Selection,
+    All,
+}
+
+impl FromStr for SubstituteScope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(SubstituteScope::Line),
+            "selection" => Ok(SubstituteScope::Selection),
+            "all" => Ok(SubstituteScope::All),
+            _ => Err(anyhow!("Unknown scope, expected line, selection or all")),
+        }
+    }
+}
+
+/// Replacement of the matches of a search between two positions, one match at
+/// a time so that each of them can be confirmed. Every match is found in the
+/// text as it was before the first replacement, so that replacements are
+/// never matched themselves.
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    /// Matches left, as char ranges of the text before any replacement, with
+    /// the text replacing them
+    pending: VecDeque<(Range<usize>, String)>,
+    current: Option<(Pos, Pos, Range<usize>, String)>,
+    /// Chars inserted by the replacements so far minus those deleted, which
+    /// is how far the matches after them moved
+    offset: isize,
+    count: usize,
+}
+
+impl Substitution {
+    /// Substitution of the matches of `search` in `buffer` lying between
+    /// `start` and `end`, which may be past the end of the buffer to cover
+    /// everything after `start`. `$1` or `${name}` in `replacement` are
+    /// expanded to the capture groups.
+    pub fn new(
+        search: &Search,
+        replacement: &str,
+        buffer: &Buffer,
+        start: Pos,
+        end: Pos,
+    ) -> Substitution {
+        let mut pending = VecDeque::new();
+        let last_y = end.y.min(buffer.content_lines_len().saturating_sub(1));
+        'lines: for y in start.y..=last_y {
+            let Some(line) = buffer.line(y) else {
+                break;
+            };
+            for captures in search.regex.captures_iter(&line) {
+                let Some(m) = captures.get(0).filter(|m| !m.is_empty()) else {
+                    continue;
+                };
+                // Part of a grapheme, like a letter without its accent, can't
+                // be replaced on its own
+                let (Some(match_start), Some(match_end)) = (
+                    grapheme_boundary(&line, m.start()),
+                    grapheme_boundary(&line, m.end()),
+                ) else {
+                    continue;
+                };
+                if y == start.y && match_start < start.x {
+                    continue;
+                }
+                if y == end.y && match_end > end.x {
+                    break 'lines;
+                }
+                let mut text = String::new();
+                captures.expand(replacement, &mut text);
+                let range = buffer.pos_to_char(Pos::new(match_start, y))
+                    ..buffer.pos_to_char(Pos::new(match_end, y));
+                pending.push_back((range, text));
+            }
+        }
+        Substitution {
+            pending,
+            current: None,
+            offset: 0,
+            count: 0,
+        }
+    }
+
+    /// Number of matches replaced so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Match waiting to be replaced or skipped
+    pub fn current(&self) -> Option<(Pos, Pos)> {
+        self.current.as_ref().map(|(start, end, ..)| (*start, *end))
+    }
+
+    /// Makes the next match the current one, where it is now that the
+    /// replacements before it moved it
+    pub fn find_next(&mut self, buffer: &Buffer) -> Option<(Pos, Pos)> {
+        self.current = None;
+        while let Some((range, text)) = self.pending.pop_front() {
+            let chars = range.start.saturating_add_signed(self.offset)
+                ..range.end.saturating_add_signed(self.offset);
+            let start = buffer.char_to_pos(chars.start);
+            let end = buffer.char_to_pos(chars.end);
+            // A replacement before it may have merged with its first grapheme
+            if start == end
+                || buffer.pos_to_char(start) != chars.start
+                || buffer.pos_to_char(end) != chars.end
+            {
+                continue;
+            }
+            self.current = Some((start, end, range, text));
+            return Some((start, end));
+        }
+        None
+    }
+
+    /// Replaces the current match, leaving the cursor after the inserted text
+    pub fn replace_current(&mut self, buffer: &mut Buffer) -> Result<()> {
+        let Some((start, end, range, text)) = self.current.take() else {
+            return Ok(());
+        };
+        buffer.replace_range(start, end, &text)?;
+        self.offset += text.chars().count() as isize - range.len() as isize;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn skip_current(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Column of the grapheme starting at byte `index` of `line`, if one does
+fn grapheme_boundary(line: &str, index: usize) -> Option<usize> {
+    let x = byte_to_grapheme(line, index);
+    (grapheme_to_byte(line, x) == index).then_some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text of `text` once every match of `pattern` between `start` and `end`
+    /// is replaced with `replacement`, along with the number of replacements
+    fn substitute(
+        text: &str,
+        pattern: &str,
+        replacement: &str,
+        start: Pos,
+        end: Pos,
+    ) -> (String, usize) {
+        let mut buffer = Buffer::load_from_str(text);
+        let search = Search::from_pattern(pattern, true).unwrap();
+        let mut substitution = Substitution::new(&search, replacement, &buffer, start, end);
+        while substitution.find_next(&buffer).is_some() {
+            substitution.replace_current(&mut buffer).unwrap();
+        }
+        (buffer.get_contents(), substitution.count())
+    }
+
+    #[test]
+    fn replaces_every_match_in_range() {
+        let end = Pos::new(usize::MAX, usize::MAX);
+        assert_eq!(
+            substitute("foo foo\nfoo", "foo", "bar", Pos::default(), end),
+            ("bar bar\nbar".to_string(), 3)
+        );
+    }
+
+    #[test]
+    fn range_end_follows_longer_and_shorter_replacements() {
+        let end = Pos::new(7, 0);
+        assert_eq!(
+            substitute("foo foo foo", "foo", "longer", Pos::default(), end),
+            ("longer longer foo".to_string(), 2)
+        );
+        assert_eq!(
+            substitute("foo foo foo", "foo", "x", Pos::default(), end),
+            ("x x foo".to_string(), 2)
+        );
+        assert_eq!(
+            substitute("foo foo foo", "foo", "x", Pos::new(4, 0), Pos::new(11, 0)),
+            ("foo x x".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn replacement_is_not_matched_again() {
+        assert_eq!(
+            substitute("aaa", "a", "aa", Pos::default(), Pos::new(3, 0)),
+            ("aaaaaa".to_string(), 3)
+        );
+    }
+
+    #[test]
+    fn anchors_only_match_the_original_text() {
+        let end = Pos::new(usize::MAX, usize::MAX);
+        assert_eq!(
+            substitute("aaa", "^a", "", Pos::default(), end),
+            ("aa".to_string(), 1)
+        );
+        assert_eq!(
+            substitute("foofoo", r"\bfoo", "", Pos::default(), end),
+            ("foo".to_string(), 1)
+        );
+        assert_eq!(
+            substitute("ab ab", "b$", "", Pos::default(), end),
+            ("ab a".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn matches_splitting_a_grapheme_are_left_alone() {
+        let end = Pos::new(usize::MAX, usize::MAX);
+        assert_eq!(
+            substitute("e\u{301} e", "e", "x", Pos::default(), end),
+            ("e\u{301} x".to_string(), 1)
+        );
+        assert_eq!(
+            substitute("e\u{301}", "\u{301}", "", Pos::default(), end),
+            ("e\u{301}".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn range_end_follows_inserted_lines() {
+        assert_eq!(
+            substitute("a\na\na", "a", "b\nc", Pos::default(), Pos::new(1, 1)),
+            ("b\nc\nb\nc\na".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn replacement_expands_capture_groups() {
+        assert_eq!(
+            substitute(
+                "id 12, id 345",
+                r"id (?<n>\d+)",
+                "#${n}",
+                Pos::default(),
+                Pos::new(13, 0)
+            ),
+            ("#12, #345".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn skipped_matches_stay() {
+        let mut buffer = Buffer::load_from_str("foo foo foo");
+        let search = Search::new("foo", true).unwrap();
+        let mut substitution =
+            Substitution::new(&search, "x", &buffer, Pos::default(), Pos::new(11, 0));
+        assert_eq!(
+            substitution.find_next(&buffer),
+            Some((Pos::new(0, 0), Pos::new(3, 0)))
+        );
+        substitution.skip_current();
+        assert_eq!(
+            substitution.find_next(&buffer),
+            Some((Pos::new(4, 0), Pos::new(7, 0)))
+        );
+        substitution.replace_current(&mut buffer).unwrap();
+        assert_eq!(
+            substitution.find_next(&buffer),
+            Some((Pos::new(6, 0), Pos::new(9, 0)))
+        );
+        substitution.skip_current();
+        assert_eq!(substitution.find_next(&buffer), None);
+        assert_eq!(buffer.get_contents(), "foo x foo");
+        assert_eq!(substitution.count(), 1);
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Sections of the status bar, aligned to the left and right edges of the
+/// window. Placeholders such as `{file}` are replaced by their value and `{{`
+/// or `}}` stand for a literal brace.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatusBarTemplate {
+    pub left: String,
+    pub right: String,
+}
+
+impl StatusBarTemplate {
+    /// Status line exactly `width` columns wide, `value` giving the text of
+    /// each placeholder or `None` for unknown ones, which are kept as is.
+    /// The left section is shortened first when both don't fit.
+    pub fn render(&self, width: usize, value: impl Fn(&str) -> Option<String>) -> String {
+        let left = expand(&self.left, &value);
+        let right = expand(&self.right, &value);
+        let right = truncate_start(&right, width);
+        let right_width = right.width();
+        // Keep a space between the sections, unless the right one is empty
+        let left_width = if right_width == 0 {
+            width
+        } else {
+            width.saturating_sub(right_width + 1)
+        };
+        let left = truncate_end(&left, left_width);
+        let gap = width.saturating_sub(left.width() + right_width);
+        format!("{left}{}{right}", " ".repeat(gap))
+    }
+}
+
+/// `template` with its placeholders replaced
+fn expand(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            expanded.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let placeholder = rest
+            .strip_prefix('{')
+            .and_then(|after| after.find('}').map(|end| &after[..end]));
+        match placeholder.and_then(|name| value(name).map(|v| (name, v))) {
+            Some((name, v)) => {
+                expanded.push_str(&v);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                expanded.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// `text` cut to `width` columns, ending with `…` if anything was cut
+pub fn truncate_end(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut truncated_width = 0;
+    for g in text.graphemes(true) {
+        if truncated_width + g.width() + 1 > width {
+            break;
+        }
+        truncated.push_str(g);
+        truncated_width += g.width();
+    }
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// `text` cut to its last `width` columns, starting with `…` if anything was
+/// cut
+fn truncate_start(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut kept = vec![];
+    let mut kept_width = 0;
+    for g in text.graphemes(true).rev() {
+        if kept_width + g.width() + 1 > width {
+            break;
+        }
+        kept.push(g);
+        kept_width += g.width();
+    }
+    let mut truncated = String::new();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated.extend(kept.into_iter().rev());
+    truncated
+}
@@ -0,0 +1,327 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::buffer::Buffer;
+
+/// Cells of the comparison table above which changed lines are shown as
+/// removed then added instead of being matched up
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Swap file keeping the unsaved changes to `document`, next to it
+pub fn swap_path(document: &Path) -> Option<PathBuf> {
+    let file_name = document.file_name()?.to_string_lossy();
+    Some(document.with_file_name(format!(".{file_name}.rudit.swp")))
+}
+
+enum Job {
+    Write {
+        document: PathBuf,
+        swap: PathBuf,
+        text: Box<Buffer>,
+    },
+    Remove(PathBuf),
+    /// Answered once every job sent before it is done
+    Flush(Sender<()>),
+}
+
+/// Background thread writing and removing the swap files of every document,
+/// so that saving them never holds up the editor
+#[derive(Debug, Clone)]
+pub struct SwapWriter {
+    jobs: Sender<Job>,
+    /// Last error of the background thread, not yet reported
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Default for SwapWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SwapWriter {
+    pub fn new() -> SwapWriter {
+        let (jobs, receiver) = mpsc::channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = Arc::clone(&error);
+        thread::spawn(move || {
+            for job in receiver {
+                match job {
+                    Job::Write {
+                        document,
+                        swap,
+                        text,
+                    } => {
+                        if let Err(err) = write_swap(&document, &swap, *text) {
+                            *thread_error.lock().unwrap() = Some(format!("{err:#}"));
+                        }
+                    }
+                    Job::Remove(swap) => {
+                        let _ = fs::remove_file(swap);
+                    }
+                    Job::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        SwapWriter { jobs, error }
+    }
+
+    /// Waits for the background thread to be done with every job sent so far,
+    /// before exiting
+    pub fn flush(&self) {
+        let (done, receiver) = mpsc::channel();
+        if self.jobs.send(Job::Flush(done)).is_ok() {
+            let _ = receiver.recv();
+        }
+    }
+
+    /// Error met by the background thread since the last call, if any
+    pub fn take_error(&self) -> Option<String> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+/// Copy of the unsaved changes to a document, kept so that they can be
+/// recovered if rudit or its terminal dies
+#[derive(Debug, Clone, Default)]
+pub struct SwapFile {
+    /// Swap file written for the document, if any
+    written: Option<PathBuf>,
+    /// Edit count of the buffer when it was last written
+    written_edits: u64,
+    last_write: Option<Instant>,
+}
+
+impl SwapFile {
+    /// Writes `buffer` to the swap file of `document` if it changed since the
+    /// last write and at least `interval` went by. Without unsaved changes
+    /// there is nothing to keep and the swap file is removed.
+    pub fn update(
+        &mut self,
+        writer: &SwapWriter,
+        document: &Path,
+        buffer: &Buffer,
+        interval: Duration,
+    ) {
+        if !buffer.is_modified() {
+            self.remove(writer);
+            return;
+        }
+        let Some(swap) = swap_path(document) else {
+            return;
+        };
+        if self.written.as_ref() != Some(&swap) {
+            // The document was renamed
+            self.remove(writer);
+        } else if self.written_edits == buffer.edit_count()
+            || self
+                .last_write
+                .is_some_and(|time| time.elapsed() < interval)
+        {
+            return;
+        }
+        let _ = writer.jobs.send(Job::Write {
+            document: document.to_path_buf(),
+            swap: swap.clone(),
+            text: Box::new(buffer.text_snapshot()),
+        });
+        self.written = Some(swap);
+        self.written_edits = buffer.edit_count();
+        self.last_write = Some(Instant::now());
+    }
+
+    /// Removes the swap file written for the document, if any
+    pub fn remove(&mut self, writer: &SwapWriter) {
+        if let Some(swap) = self.written.take() {
+            let _ = writer.jobs.send(Job::Remove(swap));
+        }
+    }
+}
+
+/// Writes the swap file atomically like any document. The swap is first given
+/// the permissions of `document`, or made private to the user if the document
+/// doesn't exist yet, and saving copies them onto the temporary file before
+/// writing to it, so that the contents are never readable by more users than
+/// the document's.
+fn write_swap(document: &Path, swap: &Path, mut text: Buffer) -> Result<()> {
+    if !swap.exists() {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(swap)?;
+    }
+    // Also for a swap left by an earlier session, the document's permissions
+    // may have changed since
+    if let Ok(metadata) = fs::metadata(document) {
+        fs::set_permissions(swap, owner_writable(metadata.permissions()))?;
+    }
+    text.save_to_file(swap)
+}
+
+/// `permissions` letting the user read and write the file, as the swap of a
+/// read-only document is still rewritten
+#[cfg(unix)]
+fn owner_writable(mut permissions: fs::Permissions) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.set_mode(permissions.mode() | 0o600);
+    permissions
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+fn owner_writable(mut permissions: fs::Permissions) -> fs::Permissions {
+    permissions.set_readonly(false);
+    permissions
+}
+
+/// Changes left in a swap file by an editor that didn't exit cleanly
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    pub swap: PathBuf,
+    pub contents: String,
+}
+
+impl Recovery {
+    /// Swap file left next to `document` if it holds something else than
+    /// `saved`, the document as loaded. A swap file identical to it has
+    /// nothing to recover and is removed.
+    pub fn find(document: &Path, saved: &Buffer) -> Option<Recovery> {
+        let swap = swap_path(document)?;
+        let contents = fs::read_to_string(&swap).ok()?;
+        if contents == saved.get_contents() {
+            let _ = fs::remove_file(&swap);
+            return None;
+        }
+        Some(Recovery { swap, contents })
+    }
+
+    /// Differences between `saved` and the recovered text
+    pub fn diff(&self, saved: &Buffer) -> String {
+        diff_lines(&saved.get_contents(), &self.contents)
+    }
+
+    /// Deletes the swap file, giving up on its changes
+    pub fn discard(&self) -> Result<()> {
+        Ok(fs::remove_file(&self.swap)?)
+    }
+}
+
+/// Every line of `old` and `new`, those only in `old` starting with `- `,
+/// those only in `new` with `+ ` and common ones with two spaces
+fn diff_lines(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+
+    let mut diff = String::new();
+    let mut push = |marker: &str, line: &str| {
+        diff.push_str(marker);
+        diff.push_str(line);
+        diff.push('\n');
+    };
+    old[..prefix].iter().for_each(|line| push("  ", line));
+    if (old_changed.len() + 1) * (new_changed.len() + 1) > MAX_DIFF_CELLS {
+        old_changed.iter().for_each(|line| push("- ", line));
+        new_changed.iter().for_each(|line| push("+ ", line));
+    } else {
+        // Longest common subsequence of the lines from each position onwards
+        let width = new_changed.len() + 1;
+        let mut common = vec![0u32; (old_changed.len() + 1) * width];
+        for i in (0..old_changed.len()).rev() {
+            for j in (0..new_changed.len()).rev() {
+                common[i * width + j] = if old_changed[i] == new_changed[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old_changed.len() || j < new_changed.len() {
+            if i < old_changed.len() && j < new_changed.len() && old_changed[i] == new_changed[j] {
+                push("  ", old_changed[i]);
+                i += 1;
+                j += 1;
+            } else if j == new_changed.len()
+                || (i < old_changed.len()
+                    && common[(i + 1) * width + j] >= common[i * width + j + 1])
+            {
+                push("- ", old_changed[i]);
+                i += 1;
+            } else {
+                push("+ ", new_changed[j]);
+                j += 1;
+            }
+        }
+    }
+    old[old.len() - suffix..]
+        .iter()
+        .for_each(|line| push("  ", line));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_of_identical_texts_has_no_change() {
+        assert_eq!(diff_lines("a\nb\n", "a\nb\n"), "  a\n  b\n");
+        assert_eq!(diff_lines("", ""), "");
+    }
+
+    #[test]
+    fn diff_marks_added_and_removed_lines() {
+        assert_eq!(diff_lines("a\nc\n", "a\nb\nc\n"), "  a\n+ b\n  c\n");
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nc\n"), "  a\n- b\n  c\n");
+        assert_eq!(diff_lines("", "a\n"), "+ a\n");
+        assert_eq!(diff_lines("a\n", ""), "- a\n");
+    }
+
+    #[test]
+    fn diff_shows_a_changed_line_as_removed_then_added() {
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nx\nc\n"), "  a\n- b\n+ x\n  c\n");
+    }
+
+    #[test]
+    fn diff_keeps_lines_common_to_both_changes() {
+        assert_eq!(
+            diff_lines("a\nb\nc\nd\ne\n", "x\nb\nd\ny\ne\n"),
+            "- a\n+ x\n  b\n- c\n  d\n+ y\n  e\n"
+        );
+    }
+
+    #[test]
+    fn diff_of_repeated_lines() {
+        assert_eq!(diff_lines("a\na\n", "a\n"), "  a\n- a\n");
+        assert_eq!(diff_lines("a\n", "a\na\na\n"), "  a\n+ a\n+ a\n");
+    }
+}
@@ -0,0 +1,87 @@
+use unicode_width::UnicodeWidthStr;
+
+use crate::status::truncate_end;
+
+/// Tab of a document in the tab bar
+#[derive(Debug, Clone)]
+pub struct Tab {
+    pub document: usize,
+    /// Column the tab starts at
+    pub x: usize,
+    pub label: String,
+}
+
+/// Tabs fitting on the width of the tab bar, scrolled so that the one of the
+/// active document is shown. Tabs hidden on either side are marked by an
+/// arrow in the first or last column.
+#[derive(Debug, Clone)]
+pub struct TabBar {
+    pub tabs: Vec<Tab>,
+    pub hidden_left: bool,
+    pub hidden_right: bool,
+}
+
+impl TabBar {
+    /// Lays out the tabs with `labels`, one per document, on `width` columns
+    pub fn new(labels: Vec<String>, active: usize, width: usize) -> TabBar {
+        let total: usize = labels.iter().map(|label| label.width()).sum();
+        if total <= width {
+            let last = labels.len();
+            return TabBar {
+                tabs: place(labels, 0..last, 0),
+                hidden_left: false,
+                hidden_right: false,
+            };
+        }
+
+        // Columns left between the arrows
+        let room = width.saturating_sub(2);
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|label| truncate_end(label, room))
+            .collect();
+        let active = active.min(labels.len().saturating_sub(1));
+        let (mut first, mut last) = (active, active);
+        let mut used = labels.get(active).map_or(0, |label| label.width());
+        // Fill the room with the tabs before the active one first, so that
+        // going through them one by one scrolls a tab at a time
+        while first > 0 && used + labels[first - 1].width() <= room {
+            first -= 1;
+            used += labels[first].width();
+        }
+        while last + 1 < labels.len() && used + labels[last + 1].width() <= room {
+            last += 1;
+            used += labels[last].width();
+        }
+
+        let hidden_right = last + 1 < labels.len();
+        TabBar {
+            tabs: place(labels, first..last + 1, 1),
+            hidden_left: first > 0,
+            hidden_right,
+        }
+    }
+
+    /// Document whose tab is shown at column `x`
+    pub fn document_at(&self, x: usize) -> Option<usize> {
+        self.tabs
+            .iter()
+            .find(|tab| (tab.x..tab.x + tab.label.width()).contains(&x))
+            .map(|tab| tab.document)
+    }
+}
+
+/// Tabs of the documents in `shown`, one after the other from column `x`
+fn place(labels: Vec<String>, shown: std::ops::Range<usize>, mut x: usize) -> Vec<Tab> {
+    labels
+        .into_iter()
+        .enumerate()
+        .take(shown.end)
+        .skip(shown.start)
+        .map(|(document, label)| {
+            let tab = Tab { document, x, label };
+            x += tab.label.width();
+            tab
+        })
+        .collect()
+}
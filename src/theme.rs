@@ -0,0 +1,270 @@
+use std::{fmt, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use crossterm::style::{self, Attribute, Attributes};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::{Color, ColorSupport},
+    config::merge_tables,
+    highlight::TokenKind,
+};
+
+/// Themes shipped with rudit, the first one being the default
+const BUILTIN_THEMES: [(&str, &str); 3] = [
+    ("default", include_str!("themes/default.toml")),
+    ("gruvbox_dark", include_str!("themes/gruvbox_dark.toml")),
+    ("ansi", include_str!("themes/ansi.toml")),
+];
+
+/// Colours of every zone of the editor
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Theme {
+    pub edit_zone: ColorPair,
+    pub selection: ColorPair,
+    pub search_match: ColorPair,
+    pub status_bar: ColorPair,
+    /// Status lines of the windows without the focus, and the borders
+    /// between windows side by side
+    pub inactive_status_bar: ColorPair,
+    pub command_zone: ColorPair,
+    pub gutter: ColorPair,
+    /// Tabs of the open buffers, and the rest of the tab bar
+    pub tab_bar: ColorPair,
+    /// Tab of the buffer being edited
+    pub active_tab: ColorPair,
+    pub syntax: SyntaxTheme,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct ColorPair {
+    pub bg: Color,
+    pub fg: Color,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub underline: bool,
+    /// Swaps the background and foreground colours
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+impl ColorPair {
+    pub fn attributes(&self) -> Attributes {
+        [
+            (self.bold, Attribute::Bold),
+            (self.italic, Attribute::Italic),
+            (self.underline, Attribute::Underlined),
+            (self.reverse, Attribute::Reverse),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .fold(Attributes::default(), |attributes, (_, attribute)| {
+            attributes | attribute
+        })
+    }
+}
+
+impl From<ColorPair> for style::Colors {
+    fn from(value: ColorPair) -> Self {
+        style::Colors::new(value.fg.into(), value.bg.into())
+    }
+}
+
+/// Foreground colour and attributes of a kind of highlighted token
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct TokenStyle {
+    pub fg: Color,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub underline: bool,
+}
+
+impl TokenStyle {
+    /// This style over the background of `zone`
+    pub fn over(&self, zone: ColorPair) -> ColorPair {
+        ColorPair {
+            bg: zone.bg,
+            fg: self.fg,
+            bold: self.bold,
+            italic: self.italic,
+            underline: self.underline,
+            reverse: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct SyntaxTheme {
+    comment: TokenStyle,
+    string: TokenStyle,
+    number: TokenStyle,
+    keyword: TokenStyle,
+    r#type: TokenStyle,
+    constant: TokenStyle,
+    function: TokenStyle,
+    variable: TokenStyle,
+    key: TokenStyle,
+    heading: TokenStyle,
+    markup: TokenStyle,
+}
+
+impl ColorPair {
+    fn quantize(self, support: ColorSupport) -> ColorPair {
+        ColorPair {
+            bg: self.bg.quantize(support),
+            fg: self.fg.quantize(support),
+            ..self
+        }
+    }
+}
+
+impl SyntaxTheme {
+    pub fn get(&self, kind: TokenKind) -> TokenStyle {
+        match kind {
+            TokenKind::Comment => self.comment,
+            TokenKind::String => self.string,
+            TokenKind::Number => self.number,
+            TokenKind::Keyword => self.keyword,
+            TokenKind::Type => self.r#type,
+            TokenKind::Constant => self.constant,
+            TokenKind::Function => self.function,
+            TokenKind::Variable => self.variable,
+            TokenKind::Key => self.key,
+            TokenKind::Heading => self.heading,
+            TokenKind::Markup => self.markup,
+        }
+    }
+}
+
+impl Theme {
+    /// Theme called `name`, either one shipped with rudit or one stored in
+    /// `~/.config/rudit/themes/<name>.toml`, `name` can also be the path of a
+    /// theme file
+    pub fn load(name: &str) -> Result<Theme> {
+        if let Some((_, theme)) = BUILTIN_THEMES.iter().find(|(n, _)| *n == name) {
+            return Theme::from_toml_str(theme);
+        }
+        let path = if name.ends_with(".toml") || name.contains(std::path::is_separator) {
+            Path::new(name).to_path_buf()
+        } else {
+            home::home_dir()
+                .context("No home directory to look for themes in")?
+                .join(".config/rudit/themes")
+                .join(format!("{name}.toml"))
+        };
+        let file_content = fs::read_to_string(&path).with_context(|| {
+            let builtin: Vec<_> = BUILTIN_THEMES.iter().map(|(n, _)| *n).collect();
+            format!(
+                "Unknown theme `{name}`, not one of {} nor a readable file at {}",
+                builtin.join(", "),
+                path.display()
+            )
+        })?;
+        Theme::from_toml_str(&file_content).with_context(|| format!("Invalid theme `{name}`"))
+    }
+
+    /// Parses a theme, zones missing from it keep the colours of the default
+    /// theme. Colours can be given as names defined in its `[palette]` table.
+    pub fn from_toml_str(s: &str) -> Result<Theme> {
+        let mut theme = resolved_table(BUILTIN_THEMES[0].1)?;
+        merge_tables(&mut theme, resolved_table(s)?);
+        Ok(theme.try_into()?)
+    }
+}
+
+impl Theme {
+    /// This theme with its colours brought down to what the terminal supports.
+    /// Without colours, zones standing out from the text are reversed instead.
+    pub fn for_color_support(self, support: ColorSupport) -> Theme {
+        let reverse_without_color = |pair: ColorPair| ColorPair {
+            reverse: pair.reverse || support == ColorSupport::None,
+            ..pair.quantize(support)
+        };
+        let quantize_token = |style: TokenStyle| TokenStyle {
+            fg: style.fg.quantize(support),
+            ..style
+        };
+        Theme {
+            edit_zone: self.edit_zone.quantize(support),
+            selection: reverse_without_color(self.selection),
+            search_match: reverse_without_color(self.search_match),
+            status_bar: reverse_without_color(self.status_bar),
+            inactive_status_bar: self.inactive_status_bar.quantize(support),
+            command_zone: reverse_without_color(self.command_zone),
+            gutter: self.gutter.quantize(support),
+            tab_bar: self.tab_bar.quantize(support),
+            active_tab: reverse_without_color(self.active_tab),
+            syntax: SyntaxTheme {
+                comment: quantize_token(self.syntax.comment),
+                string: quantize_token(self.syntax.string),
+                number: quantize_token(self.syntax.number),
+                keyword: quantize_token(self.syntax.keyword),
+                r#type: quantize_token(self.syntax.r#type),
+                constant: quantize_token(self.syntax.constant),
+                function: quantize_token(self.syntax.function),
+                variable: quantize_token(self.syntax.variable),
+                key: quantize_token(self.syntax.key),
+                heading: quantize_token(self.syntax.heading),
+                markup: quantize_token(self.syntax.markup),
+            },
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::from_toml_str(BUILTIN_THEMES[0].1).unwrap()
+    }
+}
+
+/// Theme table with the palette names replaced by the colours they stand for
+fn resolved_table(s: &str) -> Result<toml::Table> {
+    let mut table: toml::Table = toml::from_str(s)?;
+    let palette = match table.remove("palette") {
+        Some(toml::Value::Table(palette)) => palette,
+        Some(_) => return Err(anyhow!("`palette` has to be a table of colours")),
+        None => toml::Table::new(),
+    };
+    for (_, value) in table.iter_mut() {
+        resolve_palette_names(value, &palette);
+    }
+    Ok(table)
+}
+
+fn resolve_palette_names(value: &mut toml::Value, palette: &toml::Table) {
+    match value {
+        toml::Value::String(name) => {
+            if let Some(toml::Value::String(color)) = palette.get(name.as_str()) {
+                *name = color.clone();
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                resolve_palette_names(value, palette);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Replaces the colours and attributes in use with the ones of a `ColorPair`
+pub struct SetStyle(pub ColorPair);
+
+impl crossterm::Command for SetStyle {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        style::SetAttribute(Attribute::Reset).write_ansi(f)?;
+        style::SetColors(self.0.into()).write_ansi(f)?;
+        style::SetAttributes(self.0.attributes()).write_ansi(f)
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> std::io::Result<()> {
+        style::SetColors(self.0.into()).execute_winapi()
+    }
+}
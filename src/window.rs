@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{buffer::View, pos::Pos};
+
+/// One of the windows the edit zone is split into, showing a document from
+/// its own place
+#[derive(Debug, Clone, Default)]
+pub struct Window {
+    /// Index of the document shown
+    pub document: usize,
+    /// Cursor and scroll, kept by the buffer itself while the window has the
+    /// focus
+    pub view: View,
+    pub top_left: Pos,
+    /// Size on screen, status line included
+    pub size: Pos,
+    /// Columns taken by the line numbers
+    pub gutter_width: usize,
+}
+
+impl Window {
+    pub fn contains(&self, pos: Pos) -> bool {
+        (self.top_left.x..self.top_left.x + self.size.x).contains(&pos.x)
+            && (self.top_left.y..self.top_left.y + self.size.y).contains(&pos.y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SplitDirection {
+    /// Windows one above the other
+    Horizontal,
+    /// Windows side by side, with a border between them
+    Vertical,
+}
+
+/// How the edit zone is shared between windows, as a tree of splits whose
+/// leaves are indices of windows
+#[derive(Debug, Clone)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        direction: SplitDirection,
+        /// Fraction of the space given to `first`, kept as the terminal is
+        /// resized
+        share: f64,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Places every window so that they fill `size` from `top_left`, pushing
+    /// the top of each border between windows side by side with its height
+    pub fn arrange(
+        &self,
+        top_left: Pos,
+        size: Pos,
+        windows: &mut [Window],
+        borders: &mut Vec<(Pos, usize)>,
+    ) {
+        match self {
+            Layout::Window(index) => {
+                if let Some(window) = windows.get_mut(*index) {
+                    window.top_left = top_left;
+                    window.size = size;
+                }
+            }
+            Layout::Split {
+                direction,
+                share,
+                first,
+                second,
+            } => {
+                let first_len = first_len(*direction, *share, size);
+                match direction {
+                    SplitDirection::Horizontal => {
+                        first.arrange(top_left, Pos::new(size.x, first_len), windows, borders);
+                        second.arrange(
+                            top_left + (0, first_len),
+                            Pos::new(size.x, size.y - first_len),
+                            windows,
+                            borders,
+                        );
+                    }
+                    SplitDirection::Vertical => {
+                        let second_x = (first_len + 1).min(size.x);
+                        first.arrange(top_left, Pos::new(first_len, size.y), windows, borders);
+                        if first_len < size.x {
+                            borders.push((top_left + (first_len, 0), size.y));
+                        }
+                        second.arrange(
+                            top_left + (second_x, 0),
+                            Pos::new(size.x - second_x, size.y),
+                            windows,
+                            borders,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Windows in the order they appear, from top left to bottom right
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(index) => vec![*index],
+            Layout::Split { first, second, .. } => {
+                let mut windows = first.windows();
+                windows.extend(second.windows());
+                windows
+            }
+        }
+    }
+
+    fn contains(&self, window: usize) -> bool {
+        match self {
+            Layout::Window(index) => *index == window,
+            Layout::Split { first, second, .. } => {
+                first.contains(window) || second.contains(window)
+            }
+        }
+    }
+
+    /// Shares the place of `window` with `new_window`, put after it
+    pub fn split(&mut self, window: usize, new_window: usize, direction: SplitDirection) {
+        match self {
+            Layout::Window(index) if *index == window => {
+                *self = Layout::Split {
+                    direction,
+                    share: 0.5,
+                    first: Box::new(Layout::Window(window)),
+                    second: Box::new(Layout::Window(new_window)),
+                }
+            }
+            Layout::Window(_) => (),
+            Layout::Split { first, second, .. } => {
+                first.split(window, new_window, direction);
+                second.split(window, new_window, direction);
+            }
+        }
+    }
+
+    /// Gives the place of `window` to its neighbour, the windows after it
+    /// moving down one index like in the list they are stored in. The last
+    /// window can't be removed.
+    pub fn remove(&mut self, window: usize) -> bool {
+        let removed = self.remove_leaf(window);
+        if removed {
+            self.shift_indices(window);
+        }
+        removed
+    }
+
+    fn remove_leaf(&mut self, window: usize) -> bool {
+        let Layout::Split { first, second, .. } = self else {
+            return false;
+        };
+        let remaining = match (first.as_ref(), second.as_ref()) {
+            (Layout::Window(index), _) if *index == window => second.as_ref().clone(),
+            (_, Layout::Window(index)) if *index == window => first.as_ref().clone(),
+            _ => return first.remove_leaf(window) || second.remove_leaf(window),
+        };
+        *self = remaining;
+        true
+    }
+
+    fn shift_indices(&mut self, removed: usize) {
+        match self {
+            Layout::Window(index) => {
+                if *index > removed {
+                    *index -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                first.shift_indices(removed);
+                second.shift_indices(removed);
+            }
+        }
+    }
+
+    /// Grows `window` by `delta` rows or columns, or shrinks it if negative,
+    /// taking the space from its neighbour in the closest split in
+    /// `direction`. `size` is the size of the whole layout.
+    pub fn resize(
+        &mut self,
+        window: usize,
+        direction: SplitDirection,
+        delta: isize,
+        size: Pos,
+    ) -> bool {
+        let Layout::Split {
+            direction: split_direction,
+            share,
+            first,
+            second,
+        } = self
+        else {
+            return false;
+        };
+        let first_len = first_len(*split_direction, *share, size);
+        let (first_size, second_size) = match split_direction {
+            SplitDirection::Horizontal => (
+                Pos::new(size.x, first_len),
+                Pos::new(size.x, size.y - first_len),
+            ),
+            SplitDirection::Vertical => (
+                Pos::new(first_len, size.y),
+                Pos::new(size.x.saturating_sub(first_len + 1), size.y),
+            ),
+        };
+        let in_first = first.contains(window);
+        if (in_first && first.resize(window, direction, delta, first_size))
+            || (!in_first && second.resize(window, direction, delta, second_size))
+        {
+            return true;
+        }
+        if *split_direction != direction || !(in_first || second.contains(window)) {
+            return false;
+        }
+        let total = split_len(direction, size);
+        if total < 2 {
+            return false;
+        }
+        let delta = if in_first { delta } else { -delta };
+        let target = first_len.saturating_add_signed(delta).clamp(1, total - 1);
+        *share = target as f64 / total as f64;
+        true
+    }
+}
+
+/// Rows or columns split between the two sides of a split, borders excluded
+fn split_len(direction: SplitDirection, size: Pos) -> usize {
+    match direction {
+        SplitDirection::Horizontal => size.y,
+        SplitDirection::Vertical => size.x.saturating_sub(1),
+    }
+}
+
+/// Rows or columns given to the first side of a split
+fn first_len(direction: SplitDirection, share: f64, size: Pos) -> usize {
+    let total = split_len(direction, size);
+    if total < 2 {
+        return total;
+    }
+    ((total as f64 * share).round() as usize).clamp(1, total - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Top left corner and size of each window once `layout` fills `size`
+    fn arranged(layout: &Layout, size: Pos) -> Vec<(Pos, Pos)> {
+        let mut windows = vec![Window::default(); layout.windows().len()];
+        layout.arrange(Pos::default(), size, &mut windows, &mut vec![]);
+        windows
+            .iter()
+            .map(|window| (window.top_left, window.size))
+            .collect()
+    }
+
+    #[test]
+    fn split_puts_the_new_window_after() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, SplitDirection::Vertical);
+        layout.split(0, 2, SplitDirection::Horizontal);
+        assert_eq!(layout.windows(), vec![0, 2, 1]);
+        let size = Pos::new(81, 20);
+        assert_eq!(
+            arranged(&layout, size),
+            vec![
+                (Pos::new(0, 0), Pos::new(40, 10)),
+                (Pos::new(41, 0), Pos::new(40, 20)),
+                (Pos::new(0, 10), Pos::new(40, 10)),
+            ]
+        );
+        let mut borders = vec![];
+        layout.arrange(Pos::default(), size, &mut [], &mut borders);
+        assert_eq!(borders, vec![(Pos::new(40, 0), 20)]);
+    }
+
+    #[test]
+    fn resize_moves_the_split_one_cell_at_a_time() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, SplitDirection::Vertical);
+        let size = Pos::new(201, 10);
+        assert_eq!(arranged(&layout, size)[0].1.x, 100);
+
+        let mut shrunk = layout.clone();
+        assert!(shrunk.resize(0, SplitDirection::Vertical, -1, size));
+        assert_eq!(arranged(&shrunk, size)[0].1.x, 99);
+        assert!(layout.resize(0, SplitDirection::Vertical, 1, size));
+        assert_eq!(arranged(&layout, size)[0].1.x, 101);
+
+        for expected in (1..101).rev() {
+            assert!(layout.resize(0, SplitDirection::Vertical, -1, size));
+            assert_eq!(arranged(&layout, size)[0].1.x, expected);
+        }
+        for expected in 2..200 {
+            assert!(layout.resize(0, SplitDirection::Vertical, 1, size));
+            assert_eq!(arranged(&layout, size)[0].1.x, expected);
+        }
+    }
+
+    #[test]
+    fn resize_of_the_second_window_takes_from_the_first() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, SplitDirection::Horizontal);
+        let size = Pos::new(80, 24);
+        assert!(layout.resize(1, SplitDirection::Horizontal, 3, size));
+        assert_eq!(
+            arranged(&layout, size),
+            vec![
+                (Pos::new(0, 0), Pos::new(80, 9)),
+                (Pos::new(0, 9), Pos::new(80, 15)),
+            ]
+        );
+        // No split in that direction
+        assert!(!layout.resize(1, SplitDirection::Vertical, 3, size));
+    }
+
+    #[test]
+    fn resize_keeps_both_sides_at_least_one_cell() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, SplitDirection::Horizontal);
+        let size = Pos::new(80, 10);
+        layout.resize(0, SplitDirection::Horizontal, -100, size);
+        assert_eq!(arranged(&layout, size)[0].1.y, 1);
+        layout.resize(0, SplitDirection::Horizontal, 100, size);
+        assert_eq!(arranged(&layout, size)[1].1.y, 1);
+    }
+
+    #[test]
+    fn remove_gives_the_place_to_the_neighbour() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, SplitDirection::Vertical);
+        layout.split(1, 2, SplitDirection::Horizontal);
+        assert!(layout.remove(1));
+        assert_eq!(layout.windows(), vec![0, 1]);
+        let size = Pos::new(81, 20);
+        assert_eq!(
+            arranged(&layout, size),
+            vec![
+                (Pos::new(0, 0), Pos::new(40, 20)),
+                (Pos::new(41, 0), Pos::new(40, 20)),
+            ]
+        );
+        assert!(!layout.remove(5));
+        assert!(layout.remove(0));
+        assert_eq!(layout.windows(), vec![0]);
+        assert!(!layout.remove(0));
+    }
+}